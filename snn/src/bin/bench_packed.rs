@@ -0,0 +1,115 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use snn::network::neuron::Neuron;
+use snn::network::{DamageModel, Engine, FaultyElement, Network, NeuronModel};
+use std::time::{Duration, Instant};
+
+// structure of the benchmark network: number of inputs, then Neurons of each layer
+const LAYER_SIZES: [usize; 4] = [256, 1024, 1024, 10];
+const TIME_STEPS: usize = 100;
+const RUNS: usize = 10;
+const CAMPAIGN_ITERATIONS: usize = 50;
+
+/// Build a fully connected network with random weights, using a fixed seed
+fn generate_network(rng: &mut StdRng) -> Network {
+    let mut network = Network::new(
+        1000.0,
+        LAYER_SIZES[0],
+        LAYER_SIZES[LAYER_SIZES.len() - 1],
+        NeuronModel::LeakyIntegrateAndFire,
+    );
+    for layer_nr in 1..LAYER_SIZES.len() {
+        let inputs = LAYER_SIZES[layer_nr - 1];
+        let neurons = (0..LAYER_SIZES[layer_nr])
+            .map(|_| {
+                let mut neuron = Neuron::new(-55.0, -70.0, -70.0, 10.0);
+                neuron.set_weights((0..inputs).map(|_| rng.gen_range(0.0..0.2)).collect());
+                neuron.set_internal_weights(
+                    (0..LAYER_SIZES[layer_nr])
+                        .map(|_| -rng.gen_range(0.0..0.01))
+                        .collect(),
+                );
+                neuron
+            })
+            .collect::<Vec<Neuron>>();
+        network.add_layer(neurons);
+    }
+    network
+}
+
+/// Run 'network' RUNS times with the specified engine, returning the output of the
+/// last run and the average duration of a run
+fn time_runs(network: &Network, engine: Engine, input: &[Vec<bool>]) -> (Vec<Vec<bool>>, Duration) {
+    let mut snn = network.clone();
+    snn.set_engine(engine);
+
+    let mut output = Vec::new();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        snn.reset_state();
+        output = snn.run_in_place(input);
+    }
+    (output, start.elapsed() / RUNS as u32)
+}
+
+/// Perform a fault injection campaign on 'network' with the specified engine, returning
+/// its duration
+fn time_campaign(network: &Network, engine: Engine, input: &[Vec<bool>]) -> Duration {
+    let mut snn = network.clone();
+    snn.set_engine(engine);
+
+    let start = Instant::now();
+    snn.simulate(
+        vec![
+            FaultyElement::Weights,
+            FaultyElement::Thresholds,
+            FaultyElement::MembranePotentials,
+            FaultyElement::Adder,
+        ],
+        DamageModel::StuckAt1,
+        CAMPAIGN_ITERATIONS,
+        input.to_vec(),
+    )
+    .expect("the input matrix is not valid");
+    start.elapsed()
+}
+
+/// Compare the sequential engine, which updates the Neurons of each Layer as structs, with
+/// the packed one, which updates them on contiguous arrays (see PackedLayer)
+fn main() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let network = generate_network(&mut rng);
+    let input = (0..LAYER_SIZES[0])
+        .map(|_| (0..TIME_STEPS).map(|_| rng.gen_bool(0.2)).collect())
+        .collect::<Vec<Vec<bool>>>();
+
+    println!(
+        "network {:?}, {} time steps: average of {} runs",
+        LAYER_SIZES, TIME_STEPS, RUNS
+    );
+    let (sequential_output, sequential_time) = time_runs(&network, Engine::Sequential, &input);
+    let (packed_output, packed_time) = time_runs(&network, Engine::Packed, &input);
+    assert!(
+        sequential_output == packed_output,
+        "the engines produced different outputs"
+    );
+    println!("  sequential: {:?}", sequential_time);
+    println!(
+        "  packed:     {:?} ({:.2}x)",
+        packed_time,
+        sequential_time.as_secs_f64() / packed_time.as_secs_f64()
+    );
+
+    println!(
+        "fault injection campaign: {} iterations",
+        CAMPAIGN_ITERATIONS
+    );
+    let sequential_time = time_campaign(&network, Engine::Sequential, &input);
+    let packed_time = time_campaign(&network, Engine::Packed, &input);
+    println!("  sequential: {:?}", sequential_time);
+    println!(
+        "  packed:     {:?} ({:.2}x)",
+        packed_time,
+        sequential_time.as_secs_f64() / packed_time.as_secs_f64()
+    );
+}
@@ -8,7 +8,7 @@ fn main() {
 
     for _ in 0..4 {
         let mut neuron = Neuron::default();
-        neuron.weights.append(&mut vec![
+        std::sync::Arc::make_mut(&mut neuron.weights).append(&mut vec![
             Register::new(4.0),
             Register::new(4.0),
             Register::new(4.0),
@@ -19,7 +19,7 @@ fn main() {
 
     for _ in 0..3 {
         let mut neuron = Neuron::default();
-        neuron.weights.append(&mut vec![
+        std::sync::Arc::make_mut(&mut neuron.weights).append(&mut vec![
             Register::new(3.5),
             Register::new(3.5),
             Register::new(3.5),
@@ -2,7 +2,7 @@ use rand::distributions::Uniform;
 use rand::Rng;
 use snn::network::{
     json::{LayerData, NetworkData, NeuronData},
-    NeuronModel,
+    LateralInhibition, NeuronModel, ResetMode,
 };
 use std::{fs, path::Path};
 fn main() {
@@ -15,6 +15,7 @@ fn main() {
         nr_inputs,
         nr_outputs,
         model: NeuronModel::LeakyIntegrateAndFire,
+        noise: None,
         layers: Vec::new(),
     };
 
@@ -58,11 +59,16 @@ fn main() {
         }
         layer1.push(NeuronData {
             weights,
+            synapses: None,
             internal_weights,
+            bias: None,
+            delays: Vec::new(),
             v_th,
             v_rest,
             v_reset,
             tau,
+            model: None,
+            homeostasis_state: None,
         });
     }
 
@@ -83,11 +89,16 @@ fn main() {
         }
         layer2.push(NeuronData {
             weights,
+            synapses: None,
             internal_weights,
+            bias: None,
+            delays: Vec::new(),
             v_th,
             v_rest,
             v_reset,
             tau,
+            model: None,
+            homeostasis_state: None,
         });
     }
 
@@ -108,19 +119,51 @@ fn main() {
         }
         layer3.push(NeuronData {
             weights,
+            synapses: None,
             internal_weights,
+            bias: None,
+            delays: Vec::new(),
             v_th,
             v_rest,
             v_reset,
             tau,
+            model: None,
+            homeostasis_state: None,
         });
     }
 
     /*Inserting layers */
     nd.layers.append(&mut vec![
-        LayerData { neurons: layer1 },
-        LayerData { neurons: layer2 },
-        LayerData { neurons: layer3 },
+        LayerData {
+            model: None,
+            reset_mode: ResetMode::ToResetPotential,
+            inhibition: LateralInhibition::Dense,
+            homeostasis: None,
+            sources: None,
+            convolution: None,
+            kernel: Vec::new(),
+            neurons: layer1,
+        },
+        LayerData {
+            model: None,
+            reset_mode: ResetMode::ToResetPotential,
+            inhibition: LateralInhibition::Dense,
+            homeostasis: None,
+            sources: None,
+            convolution: None,
+            kernel: Vec::new(),
+            neurons: layer2,
+        },
+        LayerData {
+            model: None,
+            reset_mode: ResetMode::ToResetPotential,
+            inhibition: LateralInhibition::Dense,
+            homeostasis: None,
+            sources: None,
+            convolution: None,
+            kernel: Vec::new(),
+            neurons: layer3,
+        },
     ]);
 
     let serialized = serde_json::to_string(&nd).unwrap();
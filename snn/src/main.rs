@@ -1,6 +1,6 @@
 use clap::Parser;
 use snn::network::{self, json};
-use snn::network::{DamageModel, FaultyElement};
+use snn::network::{DamageModel, Engine, FaultyElement, NeuronParallelism};
 use std::fs::{self, File};
 use std::io::Write;
 
@@ -22,7 +22,7 @@ struct Args {
         short,
         long,
         default_value_t = String::from(
-            "weights, thresholds, membrane_potentials, reset_potentials, potentials_at_rest, comparator, adder, multiplier, divider"
+            "weights, thresholds, membrane_potentials, reset_potentials, potentials_at_rest, comparator, adder, multiplier, divider, delays, biases"
         )
     )]
     damaged_elements_list: String,
@@ -33,6 +33,27 @@ struct Args {
     /// and transient_bit_flip
     #[arg(short, long, default_value_t = String::from("stuck_at_0"))]
     type_of_damage: String,
+    /// Engine used to simulate the network, among threaded, sequential,
+    /// pipelined, concurrent and packed
+    #[arg(short, long, default_value_t = String::from("threaded"))]
+    engine: String,
+    /// restart runs with transient damages from the state saved at the
+    /// time step of the damage, instead of from the beginning (sequential
+    /// and concurrent engines only)
+    #[arg(short, long)]
+    checkpointing: bool,
+    /// stop runs with damages as soon as the state of the network converges
+    /// back to the one without damages (sequential and concurrent engines
+    /// only)
+    #[arg(long)]
+    early_termination: bool,
+    /// number of threads used to update the Neurons of wide layers
+    #[arg(long, default_value_t = 1)]
+    neuron_threads: usize,
+    /// minimum number of Neurons a layer must have to be updated by
+    /// more threads
+    #[arg(long, default_value_t = 1024)]
+    parallel_min_neurons: usize,
 }
 fn main() {
     // parse arguments
@@ -75,6 +96,12 @@ fn main() {
             "divider" => {
                 faulty_elements.push(FaultyElement::Divider);
             }
+            "delays" => {
+                faulty_elements.push(FaultyElement::Delays);
+            }
+            "biases" => {
+                faulty_elements.push(FaultyElement::Biases);
+            }
             _ => {
                 panic!("{element} is not a valid element!");
             }
@@ -98,8 +125,29 @@ fn main() {
         }
     }
 
+    // check engine
+    let engine = match args.engine.as_str() {
+        "threaded" => Engine::Threaded,
+        "sequential" => Engine::Sequential,
+        "pipelined" => Engine::Pipelined,
+        "concurrent" => Engine::Concurrent,
+        "packed" => Engine::Packed,
+        _ => {
+            panic!("{} is not a valid engine!", args.engine);
+        }
+    };
+
     // loading network from file
-    let network = network::json::load_from_file(&args.network_json);
+    let mut network = network::json::load_from_file(&args.network_json);
+    network.set_engine(engine);
+    network.set_checkpointing(args.checkpointing);
+    network.set_early_termination(args.early_termination);
+    if args.neuron_threads > 1 {
+        network.set_neuron_parallelism(Some(NeuronParallelism {
+            min_neurons: args.parallel_min_neurons,
+            threads: args.neuron_threads,
+        }));
+    }
     // loading input from file
     let input = json::InputMatrix::load_from_file(&args.input_file).0;
 
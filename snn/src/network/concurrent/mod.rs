@@ -0,0 +1,551 @@
+use crate::network::dynamics::NeuronDynamics;
+use crate::network::layer::Layer;
+use crate::network::neuron::Neuron;
+use crate::network::noise::NoiseSource;
+use crate::network::overlay;
+use crate::network::topology::Topology;
+use crate::network::{Fault, FaultyRun, LateralInhibition, LayerSource, Network};
+use crate::register::Damage;
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::Arc;
+
+/// Result of a concurrent fault simulation: the output of the network without the
+/// simulated faults and a FaultyRun for each of them, in the same order as the faults
+pub struct ConcurrentRun {
+    pub output: Vec<Vec<bool>>,
+    pub faulty_runs: Vec<FaultyRun>,
+}
+
+/// State of a layer of a faulty machine which differs from the one of the same layer
+/// of the network without damages (the golden one)
+#[derive(Default)]
+struct DivergentLayer {
+    neurons: BTreeMap<usize, Neuron>, // Neurons whose state differs, by index
+    emitted_pulse_sources: Option<Vec<usize>>, // Neurons which fired, if they differ
+    noise_source: Option<NoiseSource>, // noise source, if its state differs
+}
+
+impl DivergentLayer {
+    /// Check whether the layer has the same state as the golden one
+    fn is_empty(&self) -> bool {
+        self.neurons.is_empty()
+            && self.emitted_pulse_sources.is_none()
+            && self.noise_source.is_none()
+    }
+}
+
+/// A FaultyMachine is a copy of the network affected by a single Fault, simulated
+/// together with the golden network: only the state which differs from the golden
+/// one is kept
+struct FaultyMachine {
+    fault: Fault,
+    layers: Vec<DivergentLayer>,
+    // pulses emitted by the last layer, by output column, where they differ from the golden ones
+    divergent_output: Vec<(usize, Vec<usize>)>,
+    masked_at: Option<usize>,
+}
+
+impl FaultyMachine {
+    /// Check whether the Fault affects the Registers of a layer at the specified time step
+    fn fault_is_active(&self, layer: usize, time_step: usize) -> bool {
+        self.fault.address.layer == layer
+            && match self.fault.damage {
+                Damage::TransientBitFlip {
+                    time_step: flip_time_step,
+                    ..
+                } => flip_time_step == time_step,
+                Damage::StuckAt0 { .. } | Damage::StuckAt1 { .. } => true,
+                Damage::Working => false,
+            }
+    }
+
+    /// Check whether the Fault can still affect the network after the specified time step
+    fn fault_is_active_after(&self, time_step: usize) -> bool {
+        match self.fault.damage {
+            Damage::TransientBitFlip {
+                time_step: flip_time_step,
+                ..
+            } => flip_time_step > time_step,
+            Damage::StuckAt0 { .. } | Damage::StuckAt1 { .. } => true,
+            Damage::Working => false,
+        }
+    }
+
+    /// Indexes of the Neurons of a layer of the machine which can differ from the golden
+    /// ones after the time step, if they can be simulated on their own (see
+    /// Layer::step_neurons): the Neurons whose state already differs and the one damaged
+    /// by the Fault, while it is active. All the other Neurons evolve as the golden ones if
+    /// the layer does not use noise ('has_noise') nor k-winner-take-all inhibition, the
+    /// machine receives the same pulses as the golden layer ('receives_golden_pulses') and
+    /// emitted the same ones during the previous time step, and the Fault does not damage
+    /// the kernel shared by all the Neurons of the layer.
+    fn independent_neurons(
+        &self,
+        layer_nr: usize,
+        time_step: usize,
+        golden_layer: &Layer,
+        has_noise: bool,
+        receives_golden_pulses: bool,
+    ) -> Option<Vec<usize>> {
+        let divergent_layer = &self.layers[layer_nr];
+        let fault_is_active = self.fault_is_active(layer_nr, time_step);
+        if has_noise
+            || matches!(
+                golden_layer.inhibition,
+                LateralInhibition::KWinnerTakeAll { .. }
+            )
+            || !receives_golden_pulses
+            || divergent_layer.emitted_pulse_sources.is_some()
+            || (fault_is_active && overlay::is_kernel_fault(golden_layer, &self.fault))
+        {
+            return None;
+        }
+
+        let mut neuron_nrs = divergent_layer.neurons.keys().copied().collect::<Vec<_>>();
+        if fault_is_active && !neuron_nrs.contains(&self.fault.address.neuron) {
+            neuron_nrs.push(self.fault.address.neuron);
+            neuron_nrs.sort_unstable();
+        }
+        Some(neuron_nrs)
+    }
+
+    /// Simulate the Neurons of a layer of the machine with the provided indexes (see
+    /// independent_neurons) in place of those of the golden layer before the time step
+    /// ('golden_layer'): the Neurons of the machine are swapped in and, once simulated,
+    /// back out, so that the golden layer is left as it was. The simulated Neurons are
+    /// returned, by index, together with the indexes of those which fired.
+    #[allow(clippy::too_many_arguments)]
+    fn step_neurons(
+        &mut self,
+        layer_nr: usize,
+        neuron_nrs: &[usize],
+        golden_layer: &mut Layer,
+        pulse_sources: &[usize],
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        golden_faults: &[Fault],
+    ) -> (Vec<(usize, Neuron)>, Vec<usize>) {
+        let divergent_neurons = &mut self.layers[layer_nr].neurons;
+        let mut neurons = neuron_nrs
+            .iter()
+            .map(|neuron_nr| {
+                let neuron = divergent_neurons
+                    .remove(neuron_nr)
+                    .unwrap_or_else(|| golden_layer.neurons[*neuron_nr].clone());
+                (*neuron_nr, neuron)
+            })
+            .collect::<Vec<_>>();
+        let mut layer_faults = golden_faults.to_vec();
+        if self.fault.address.layer == layer_nr {
+            layer_faults.push(self.fault);
+        }
+
+        let swap = |neurons: &mut Vec<(usize, Neuron)>, golden_layer: &mut Layer| {
+            for (neuron_nr, neuron) in neurons.iter_mut() {
+                mem::swap(&mut golden_layer.neurons[*neuron_nr], neuron);
+            }
+        };
+        swap(&mut neurons, golden_layer);
+        let emitted_pulse_sources = golden_layer.step_neurons(
+            neuron_nrs,
+            pulse_sources,
+            time_step,
+            time_step_duration_ms,
+            neuron_models,
+            &layer_faults,
+        );
+        swap(&mut neurons, golden_layer);
+
+        (neurons, emitted_pulse_sources)
+    }
+
+    /// Keep only the Neurons simulated by step_neurons ('neurons') which differ from those
+    /// of the golden layer after the time step ('golden_layer'), and return the pulses
+    /// emitted by the layer of the machine if they differ from the golden ones
+    /// ('golden_pulse_sources'): the other Neurons fired as the golden ones did.
+    fn keep_divergent_neurons(
+        &mut self,
+        layer_nr: usize,
+        neurons: Vec<(usize, Neuron)>,
+        fired_neurons: Vec<usize>,
+        golden_layer: &Layer,
+        golden_pulse_sources: &[usize],
+    ) -> Option<Vec<usize>> {
+        let mut emitted_pulse_sources = golden_pulse_sources
+            .iter()
+            .copied()
+            .filter(|source| neurons.iter().all(|(neuron_nr, _)| neuron_nr != source))
+            .chain(fired_neurons)
+            .collect::<Vec<usize>>();
+        emitted_pulse_sources.sort_unstable();
+
+        let divergent_layer = &mut self.layers[layer_nr];
+        divergent_layer
+            .neurons
+            .extend(neurons.into_iter().filter(|(neuron_nr, neuron)| {
+                !neuron.has_same_state(&golden_layer.neurons[*neuron_nr])
+            }));
+        divergent_layer.emitted_pulse_sources =
+            (emitted_pulse_sources != golden_pulse_sources).then_some(emitted_pulse_sources);
+        divergent_layer.emitted_pulse_sources.clone()
+    }
+
+    /// Build the layer of the machine, starting from the state of the golden layer
+    /// before the time step ('golden_layer') and replacing the state which differs
+    fn diverged_layer(&self, layer_nr: usize, golden_layer: &Layer) -> Layer {
+        let divergent_layer = &self.layers[layer_nr];
+        let mut layer = golden_layer.clone();
+        for (neuron_nr, neuron) in divergent_layer.neurons.iter() {
+            layer.neurons[*neuron_nr] = neuron.clone();
+        }
+        if let Some(emitted_pulse_sources) = &divergent_layer.emitted_pulse_sources {
+            layer
+                .emitted_pulse_sources
+                .clone_from(emitted_pulse_sources);
+        }
+        layer
+    }
+
+    /// Keep only the state of 'layer' which differs from the golden layer after the
+    /// time step ('golden_layer')
+    fn keep_divergent_state(
+        &mut self,
+        layer_nr: usize,
+        layer: Layer,
+        noise_source: Option<NoiseSource>,
+        golden_layer: &Layer,
+        golden_noise_source: &Option<NoiseSource>,
+    ) {
+        let divergent_layer = &mut self.layers[layer_nr];
+        divergent_layer.neurons = layer
+            .neurons
+            .into_iter()
+            .enumerate()
+            .filter(|(neuron_nr, neuron)| !neuron.has_same_state(&golden_layer.neurons[*neuron_nr]))
+            .collect();
+        divergent_layer.emitted_pulse_sources = (layer.emitted_pulse_sources
+            != golden_layer.emitted_pulse_sources)
+            .then_some(layer.emitted_pulse_sources);
+        divergent_layer.noise_source = if noise_source != *golden_noise_source {
+            noise_source
+        } else {
+            None
+        };
+    }
+}
+
+/// Simulate the network without damages (the golden network) together with a faulty
+/// machine for each of the provided faults, in a single pass over the time steps and
+/// the layers of the network, as done by concurrent fault simulators of digital circuits.
+///
+/// At each time step, each layer is first simulated for the golden network; then it is
+/// simulated again only for the machines which can behave differently, i.e. those
+/// whose Fault is active in the layer, whose state of the layer differs from the golden
+/// one or which received different pulses from the sources of the layer, including the
+/// pulses fed back from the previous time step. Each machine keeps
+/// only the state of the Neurons which differ from the golden ones, so that many faults
+/// can be simulated at once. When the Neurons of the layer do not depend on each other
+/// during the time step (see Layer::step_neurons), a machine only simulates those which can
+/// differ, in place of the golden ones; otherwise it simulates a copy of the golden layer.
+///
+/// A machine is considered masked once its Fault is no longer active and its state is
+/// the same as the golden one: from then on it is not simulated anymore.
+///
+/// The golden network starts from the current state and time step of 'network' (see
+/// Network::time_step), which keeps its state at the end of the run. The Faults applied to the network (see apply_damage) affect
+/// both the golden network and all the machines.
+pub fn run(network: &mut Network, input: &[Vec<bool>], faults: &[Fault]) -> ConcurrentRun {
+    for fault in faults {
+        assert!(
+            network.register(fault.address).is_some(),
+            "Cannot simulate the fault: the damaged register does not exist"
+        );
+    }
+
+    // Number of time steps to simulate in the SNN, equal to the number of columns
+    // of the 'input' matrix
+    let snn_time_steps_number = input[0].len();
+
+    //Time step duration converted to milliseconds to perform computation later
+    let time_step_duration_ms = network.time_step_duration_us / 1000.0;
+
+    let mut output = network.create_output_matrix(snn_time_steps_number);
+    let neuron_models = network
+        .layers
+        .iter()
+        .map(|layer| layer.resolve_models(&network.model))
+        .collect::<Vec<_>>();
+    let mut noise_sources = (0..network.layers.len())
+        .map(|layer_nr| network.noise.map(|noise| NoiseSource::new(noise, layer_nr)))
+        .collect::<Vec<_>>();
+
+    let mut machines = faults
+        .iter()
+        .map(|fault| FaultyMachine {
+            fault: *fault,
+            layers: (0..network.layers.len())
+                .map(|_| DivergentLayer::default())
+                .collect(),
+            divergent_output: Vec::new(),
+            masked_at: None,
+        })
+        .collect::<Vec<_>>();
+
+    let topology = Topology::of(network);
+    let first_time_step = network.time_step;
+
+    for column in 0..snn_time_steps_number {
+        let time_step = first_time_step + column;
+        // entrances of the network which received a pulse
+        let input_pulse_sources = input
+            .iter()
+            .enumerate()
+            .filter(|(_, entrance)| entrance[column])
+            .map(|(input_node, _)| input_node)
+            .collect::<Vec<usize>>();
+
+        // pulses emitted by each layer of the golden network during the time step
+        let mut golden_emitted_pulses = Vec::<Vec<usize>>::with_capacity(network.layers.len());
+        // pulses emitted by each layer of each machine, if they differ from the golden ones
+        let mut machine_emitted_pulses =
+            vec![Vec::<Option<Vec<usize>>>::with_capacity(network.layers.len()); machines.len()];
+
+        // pulses emitted by the layers of the golden network during the previous time step,
+        // fed back to the layers receiving them, and those of each machine, if they differ
+        let golden_fed_back_pulses = topology.fed_back_pulses(time_step, |layer_nr| {
+            &network.layers[layer_nr].emitted_pulse_sources
+        });
+        let machine_fed_back_pulses = machines
+            .iter()
+            .map(|machine| {
+                machine
+                    .layers
+                    .iter()
+                    .enumerate()
+                    .map(|(layer_nr, divergent_layer)| {
+                        divergent_layer
+                            .emitted_pulse_sources
+                            .as_ref()
+                            .filter(|_| time_step > 0 && topology.feeds_back(layer_nr))
+                            .cloned()
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        for layer_nr in 0..network.layers.len() {
+            let golden_faults = network.faults.of_layer(layer_nr);
+
+            // machines which have to simulate the layer
+            let diverging_machines = machines
+                .iter()
+                .enumerate()
+                .filter(|(machine_nr, machine)| {
+                    machine.masked_at.is_none()
+                        && (machine.fault_is_active(layer_nr, time_step)
+                            || !machine.layers[layer_nr].is_empty()
+                            || machine_emitted_pulses[*machine_nr].iter().enumerate().any(
+                                |(source_layer_nr, emitted_pulses)| {
+                                    emitted_pulses.is_some()
+                                        && topology.receives_from(
+                                            layer_nr,
+                                            LayerSource::Layer(source_layer_nr),
+                                        )
+                                },
+                            )
+                            || machine_fed_back_pulses[*machine_nr].iter().enumerate().any(
+                                |(source_layer_nr, fed_back_pulses)| {
+                                    fed_back_pulses.is_some()
+                                        && topology.receives_from(
+                                            layer_nr,
+                                            LayerSource::Feedback(source_layer_nr),
+                                        )
+                                },
+                            ))
+                })
+                .map(|(machine_nr, _)| machine_nr)
+                .collect::<Vec<usize>>();
+
+            // pulses received by the golden layer, emitted by its sources
+            let layer_pulse_sources = topology.gather(
+                layer_nr,
+                &input_pulse_sources,
+                |source_layer_nr| &golden_emitted_pulses[source_layer_nr],
+                |source_layer_nr| &golden_fed_back_pulses[source_layer_nr],
+            );
+
+            // machines whose Neurons can be simulated on their own only simulate those which
+            // can differ, in place of the golden ones; the others simulate a copy of the
+            // whole golden layer, starting from its state before the time step
+            let mut independent_machines = Vec::new();
+            let mut dependent_machines = Vec::new();
+            for machine_nr in diverging_machines {
+                // pulses received by the layer of the machine, emitted by the layers
+                // of the machine itself when they differ from the golden ones
+                let emitted_pulses = &machine_emitted_pulses[machine_nr];
+                let fed_back_pulses = &machine_fed_back_pulses[machine_nr];
+                let received_pulse_sources = topology.gather(
+                    layer_nr,
+                    &input_pulse_sources,
+                    |source_layer_nr| {
+                        emitted_pulses[source_layer_nr]
+                            .as_deref()
+                            .unwrap_or(&golden_emitted_pulses[source_layer_nr])
+                    },
+                    |source_layer_nr| {
+                        fed_back_pulses[source_layer_nr]
+                            .as_deref()
+                            .unwrap_or(&golden_fed_back_pulses[source_layer_nr])
+                    },
+                );
+
+                let machine = &mut machines[machine_nr];
+                let golden_layer = &mut network.layers[layer_nr];
+                match machine.independent_neurons(
+                    layer_nr,
+                    time_step,
+                    golden_layer,
+                    noise_sources[layer_nr].is_some(),
+                    received_pulse_sources == layer_pulse_sources,
+                ) {
+                    Some(neuron_nrs) => {
+                        let (neurons, fired_neurons) = machine.step_neurons(
+                            layer_nr,
+                            &neuron_nrs,
+                            golden_layer,
+                            &received_pulse_sources,
+                            time_step,
+                            time_step_duration_ms,
+                            &neuron_models[layer_nr],
+                            golden_faults,
+                        );
+                        independent_machines.push((machine_nr, neurons, fired_neurons));
+                    }
+                    None => dependent_machines.push((machine_nr, received_pulse_sources)),
+                }
+            }
+
+            // state of the golden layer before the time step, the dependent machines start from it
+            let golden_state = (!dependent_machines.is_empty()).then(|| {
+                (
+                    network.layers[layer_nr].clone(),
+                    noise_sources[layer_nr].clone(),
+                )
+            });
+
+            let golden_pulse_sources = network.layers[layer_nr].step(
+                &layer_pulse_sources,
+                time_step,
+                time_step_duration_ms,
+                &neuron_models[layer_nr],
+                noise_sources[layer_nr].as_mut(),
+                golden_faults,
+            );
+
+            // machines which do not simulate the layer emit the golden pulses
+            for emitted_pulses in machine_emitted_pulses.iter_mut() {
+                emitted_pulses.push(None);
+            }
+
+            for (machine_nr, neurons, fired_neurons) in independent_machines {
+                machine_emitted_pulses[machine_nr][layer_nr] = machines[machine_nr]
+                    .keep_divergent_neurons(
+                        layer_nr,
+                        neurons,
+                        fired_neurons,
+                        &network.layers[layer_nr],
+                        &golden_pulse_sources,
+                    );
+            }
+
+            let Some((golden_layer_before, golden_noise_before)) = golden_state else {
+                golden_emitted_pulses.push(golden_pulse_sources);
+                continue;
+            };
+
+            for (machine_nr, received_pulse_sources) in dependent_machines {
+                let machine = &mut machines[machine_nr];
+
+                let mut layer = machine.diverged_layer(layer_nr, &golden_layer_before);
+                let mut noise_source = machine.layers[layer_nr]
+                    .noise_source
+                    .clone()
+                    .or_else(|| golden_noise_before.clone());
+                let mut layer_faults = golden_faults.to_vec();
+                if machine.fault.address.layer == layer_nr {
+                    layer_faults.push(machine.fault);
+                }
+
+                let emitted_pulse_sources = layer.step(
+                    &received_pulse_sources,
+                    time_step,
+                    time_step_duration_ms,
+                    &neuron_models[layer_nr],
+                    noise_source.as_mut(),
+                    &layer_faults,
+                );
+
+                machine.keep_divergent_state(
+                    layer_nr,
+                    layer,
+                    noise_source,
+                    &network.layers[layer_nr],
+                    &noise_sources[layer_nr],
+                );
+                machine_emitted_pulses[machine_nr][layer_nr] = (emitted_pulse_sources
+                    != golden_pulse_sources)
+                    .then_some(emitted_pulse_sources);
+            }
+
+            golden_emitted_pulses.push(golden_pulse_sources);
+        }
+
+        // write the golden output and the output of the machines which differ from it,
+        // i.e. the pulses emitted by the last layer
+        for source_index in golden_emitted_pulses.last().into_iter().flatten() {
+            output[*source_index][column] = true;
+        }
+        for (machine, mut emitted_pulses) in machines.iter_mut().zip(machine_emitted_pulses) {
+            if let Some(pulse_sources) = emitted_pulses.pop().flatten() {
+                machine.divergent_output.push((column, pulse_sources));
+            }
+        }
+
+        // machines which converged back to the golden network are masked
+        for machine in machines.iter_mut() {
+            if machine.masked_at.is_none()
+                && !machine.fault_is_active_after(time_step)
+                && machine.layers.iter().all(|layer| layer.is_empty())
+            {
+                machine.masked_at = Some(column);
+            }
+        }
+    }
+    network.time_step += snn_time_steps_number;
+
+    let faulty_runs = machines
+        .into_iter()
+        .map(|machine| {
+            let mut faulty_output = output.clone();
+            for (column, pulse_sources) in machine.divergent_output {
+                for row in faulty_output.iter_mut() {
+                    row[column] = false;
+                }
+                for source_index in pulse_sources {
+                    faulty_output[source_index][column] = true;
+                }
+            }
+            FaultyRun {
+                output: faulty_output,
+                masked_at: machine.masked_at,
+            }
+        })
+        .collect();
+
+    ConcurrentRun {
+        output,
+        faulty_runs,
+    }
+}
@@ -0,0 +1,106 @@
+use crate::register::Register;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// The struct Convolution describes the geometry of a convolutional layer, whose Neurons
+/// are arranged in output channels of output_height x output_width positions. Each Neuron
+/// receives pulses from a kernel_size x kernel_size window of each input channel, moved by
+/// 'stride' positions from one Neuron to the following one, and the input is surrounded
+/// by 'padding' positions which never emit pulses:
+///
+/// - input_channels, input_height, input_width: shape of the pulses received by the
+///   layer. Source 'i' is at (channel, row, column) in row-major order, i.e.
+///   i = (channel * input_height + row) * input_width + column
+/// - output_channels: number of kernels, i.e. of feature maps produced by the layer
+/// - kernel_size, stride, padding: size, step and border of the window
+///
+/// Neurons are ordered like the sources, by (output channel, row, column). All the
+/// Neurons of an output channel use the same kernel weights (see Kernel).
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Convolution {
+    pub input_channels: usize,
+    pub input_height: usize,
+    pub input_width: usize,
+    pub output_channels: usize,
+    pub kernel_size: usize,
+    pub stride: usize,
+    pub padding: usize,
+}
+
+impl Convolution {
+    /// Get the number of rows of each output channel
+    pub fn output_height(&self) -> usize {
+        (self.input_height + 2 * self.padding - self.kernel_size) / self.stride + 1
+    }
+
+    /// Get the number of columns of each output channel
+    pub fn output_width(&self) -> usize {
+        (self.input_width + 2 * self.padding - self.kernel_size) / self.stride + 1
+    }
+
+    /// Get the number of sources the layer receives pulses from
+    pub fn input_len(&self) -> usize {
+        self.input_channels * self.input_height * self.input_width
+    }
+
+    /// Get the number of Neurons of the layer
+    pub fn output_len(&self) -> usize {
+        self.output_channels * self.output_height() * self.output_width()
+    }
+
+    /// Get the number of weights of the kernels of all the output channels, which are
+    /// ordered by (output channel, input channel, kernel row, kernel column)
+    pub fn kernel_len(&self) -> usize {
+        self.output_channels * self.input_channels * self.kernel_size * self.kernel_size
+    }
+
+    /// Get the index of the kernel weight of the synapse going from source 'input_index'
+    /// to Neuron 'output_index', if the source is inside the window of the Neuron
+    pub fn kernel_index(&self, output_index: usize, input_index: usize) -> Option<usize> {
+        let output_area = self.output_height() * self.output_width();
+        let output_channel = output_index / output_area;
+        let output_row = output_index % output_area / self.output_width();
+        let output_column = output_index % self.output_width();
+
+        let input_area = self.input_height * self.input_width;
+        let input_channel = input_index / input_area;
+        let input_row = input_index % input_area / self.input_width;
+        let input_column = input_index % self.input_width;
+        if output_channel >= self.output_channels || input_channel >= self.input_channels {
+            return None;
+        }
+
+        // position of the source inside the window, which starts 'padding' positions
+        // before the first source
+        let kernel_row = (input_row + self.padding).checked_sub(output_row * self.stride)?;
+        let kernel_column =
+            (input_column + self.padding).checked_sub(output_column * self.stride)?;
+        if kernel_row >= self.kernel_size || kernel_column >= self.kernel_size {
+            return None;
+        }
+
+        Some(
+            ((output_channel * self.input_channels + input_channel) * self.kernel_size
+                + kernel_row)
+                * self.kernel_size
+                + kernel_column,
+        )
+    }
+}
+
+/// The ReceptiveField of a Neuron of a convolutional layer identifies its position in
+/// the layer, from which the synapse of each source is found (see Convolution::kernel_index)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ReceptiveField {
+    pub convolution: Convolution,
+    pub output_index: usize, // index of the Neuron in the layer
+}
+
+/// The Kernel of a convolutional layer keeps the weights shared by all its Neurons: the
+/// 'weights' of each Neuron are the same Vec of Registers, so that a damaged kernel weight
+/// affects every output position (see overlay::arm)
+#[derive(Clone)]
+pub struct Kernel {
+    pub convolution: Convolution,
+    pub weights: Arc<Vec<Register>>,
+}
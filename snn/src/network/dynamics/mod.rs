@@ -0,0 +1,187 @@
+use crate::network::neuron::Neuron;
+use crate::register::Register;
+
+use super::{NeuronModel, ResetMode};
+
+/// The NeuronDynamics trait describes how a Neuron evolves over time: how its
+/// membrane potential is updated when pulses are received, when it fires and how
+/// it is reset after firing.
+///
+/// The built-in models (LeakyIntegrateAndFire and IntegrateAndFire) implement
+/// this trait, but any user type can implement it as well and be used by the
+/// Network, its Layers or single Neurons.
+///
+/// Implementors should perform all arithmetic through the Register operations
+/// (Register::add, Register::mult, ...) and through the Registers of the Neuron
+/// (add_reg, mul_reg, cmp_reg, div_reg), so that damages applied to the Neuron
+/// elements keep affecting the simulation.
+pub trait NeuronDynamics: Send + Sync {
+    /// Update the membrane potential (v_mem) of the Neuron, given the Register
+    /// holding the sum of the weights of the synapses which delivered a pulse.
+    /// neuron.last_received_pulse_step still contains the time step of the previous
+    /// update when this function is called.
+    fn update_membrane_potential(
+        &self,
+        neuron: &mut Neuron,
+        pulses_contribution: Register,
+        time_step: usize,
+        time_step_duration_ms: f64,
+    );
+
+    /// Check whether the Neuron fires, by default when v_mem reaches the threshold
+    /// (v_th). The comparison is performed by the comparator of the Neuron.
+    fn fires(&self, neuron: &mut Neuron, time_step: usize) -> bool {
+        Register::cmp(neuron.v_mem, neuron.v_th, &mut neuron.cmp_reg, time_step);
+        neuron.cmp_reg.read_value(Some(time_step)).unwrap() >= 0.0
+    }
+
+    /// Return the built-in model corresponding to this implementation, if any. It is
+    /// used to export the network to json, where only built-in models can be described.
+    fn builtin_model(&self) -> Option<NeuronModel> {
+        None
+    }
+
+    /// Reset the membrane potential after the Neuron fired, according to the reset
+    /// mode of its layer: by default v_reset is copied into v_mem, v_th is subtracted
+    /// from v_mem (using the adder) or v_mem is left unchanged.
+    fn reset(&self, neuron: &mut Neuron, reset_mode: ResetMode, time_step: usize) {
+        match reset_mode {
+            ResetMode::ToResetPotential => {
+                neuron.v_reset.copy_to(&mut neuron.v_mem, time_step);
+            }
+            ResetMode::BySubtraction => {
+                Register::sub(neuron.v_mem, neuron.v_th, &mut neuron.add_reg, time_step);
+                neuron.add_reg.copy_to(&mut neuron.v_mem, time_step);
+            }
+            ResetMode::NoReset => {}
+        }
+    }
+}
+
+/// Leaky Integrate and Fire model: the membrane potential decays exponentially
+/// towards v_rest between two updates, with time constant tau.
+#[derive(Clone, Copy)]
+pub struct LeakyIntegrateAndFire;
+
+impl NeuronDynamics for LeakyIntegrateAndFire {
+    fn builtin_model(&self) -> Option<NeuronModel> {
+        Some(NeuronModel::LeakyIntegrateAndFire)
+    }
+
+    fn update_membrane_potential(
+        &self,
+        neuron: &mut Neuron,
+        pulses_contribution: Register,
+        time_step: usize,
+        time_step_duration_ms: f64,
+    ) {
+        // computing v_mem + pulses contribution
+        let mut pulses_contrib_reg = Register::new(0.0);
+        Register::add(
+            neuron.v_mem,
+            pulses_contribution,
+            &mut neuron.add_reg,
+            time_step,
+        );
+        neuron.add_reg.copy_to(&mut pulses_contrib_reg, time_step);
+
+        // computing v_mem - v_rest
+        let mut vm_vr = Register::new(0.0);
+        Register::sub(neuron.v_mem, neuron.v_rest, &mut neuron.add_reg, time_step);
+        neuron.add_reg.copy_to(&mut vm_vr, time_step);
+
+        // computing last_received_pulse_step - time_step
+        let diff_steps = Register::new(neuron.last_received_pulse_step as f64 - time_step as f64);
+
+        // computing exp argument
+        let mut exp_arg = Register::new(0.0);
+        Register::mult(
+            diff_steps,
+            Register::new(time_step_duration_ms),
+            &mut neuron.mul_reg,
+            time_step,
+        );
+        Register::div(neuron.mul_reg, neuron.tau, &mut neuron.div_reg, time_step);
+        neuron.div_reg.copy_to(&mut exp_arg, time_step);
+
+        // performing exp
+        let exp_res = Register::new(exp_arg.read_value(Some(time_step)).unwrap().exp());
+
+        // computing exp * (v_mem - v_rest)
+        let mut decay_part = Register::new(0.0);
+        Register::mult(exp_res, vm_vr, &mut neuron.mul_reg, time_step);
+        neuron.mul_reg.copy_to(&mut decay_part, time_step);
+
+        // computing decay_part + pulses_contrib_reg
+        Register::add(
+            decay_part,
+            pulses_contrib_reg,
+            &mut neuron.add_reg,
+            time_step,
+        );
+        neuron.add_reg.copy_to(&mut neuron.v_mem, time_step);
+    }
+}
+
+/// Integrate and Fire model: the membrane potential only changes when pulses
+/// are received, with no leakage.
+#[derive(Clone, Copy)]
+pub struct IntegrateAndFire;
+
+impl NeuronDynamics for IntegrateAndFire {
+    fn builtin_model(&self) -> Option<NeuronModel> {
+        Some(NeuronModel::IntegrateAndFire)
+    }
+
+    fn update_membrane_potential(
+        &self,
+        neuron: &mut Neuron,
+        pulses_contribution: Register,
+        time_step: usize,
+        _time_step_duration_ms: f64,
+    ) {
+        // computing v_mem + pulses contribution
+        let mut pulses_contrib_reg = Register::new(0.0);
+        Register::add(
+            neuron.v_mem,
+            pulses_contribution,
+            &mut neuron.add_reg,
+            time_step,
+        );
+        neuron.add_reg.copy_to(&mut pulses_contrib_reg, time_step);
+
+        pulses_contrib_reg.copy_to(&mut neuron.v_mem, time_step);
+    }
+}
+
+/// NeuronModel lists the built-in models, which can be selected from the json
+/// description of the network: each variant dispatches to the corresponding
+/// implementation of NeuronDynamics.
+impl NeuronDynamics for NeuronModel {
+    fn builtin_model(&self) -> Option<NeuronModel> {
+        Some(*self)
+    }
+
+    fn update_membrane_potential(
+        &self,
+        neuron: &mut Neuron,
+        pulses_contribution: Register,
+        time_step: usize,
+        time_step_duration_ms: f64,
+    ) {
+        match self {
+            NeuronModel::LeakyIntegrateAndFire => LeakyIntegrateAndFire.update_membrane_potential(
+                neuron,
+                pulses_contribution,
+                time_step,
+                time_step_duration_ms,
+            ),
+            NeuronModel::IntegrateAndFire => IntegrateAndFire.update_membrane_potential(
+                neuron,
+                pulses_contribution,
+                time_step,
+                time_step_duration_ms,
+            ),
+        }
+    }
+}
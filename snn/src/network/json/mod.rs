@@ -1,8 +1,13 @@
-use crate::network::{neuron::Neuron, Network};
+use crate::network::convolution::Convolution;
+use crate::network::neuron::HomeostasisState;
+use crate::network::{dynamics::NeuronDynamics, layer::Layer, neuron::Neuron, Network};
+use crate::register::Register;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::sync::Arc;
+use std::{error, fmt, fs};
 
-use super::NeuronModel;
+use super::noise::NoiseModel;
+use super::{Homeostasis, LateralInhibition, LayerSource, NeuronModel, ResetMode};
 
 #[derive(Serialize, Deserialize)]
 pub struct NetworkData {
@@ -10,22 +15,68 @@ pub struct NetworkData {
     pub nr_inputs: usize,
     pub nr_outputs: usize,
     pub model: NeuronModel,
+    /// noise affecting the neurons: if missing, neurons are deterministic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise: Option<NoiseModel>,
     pub layers: Vec<LayerData>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct LayerData {
+    /// model used by the layer neurons: if missing, the network one is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<NeuronModel>,
+    /// reset mechanism of the layer neurons: if missing, v_mem is reset to v_reset
+    #[serde(default)]
+    pub reset_mode: ResetMode,
+    /// lateral inhibition scheme of the layer: if missing, internal_weights are used
+    #[serde(default)]
+    pub inhibition: LateralInhibition,
+    /// threshold regulation of the layer neurons: if missing, thresholds are fixed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homeostasis: Option<Homeostasis>,
+    /// entrances of the network, earlier layers and layers fed back (e.g. {"Feedback": 2})
+    /// the layer receives pulses from, in the order the synapses of its neurons refer to
+    /// them: if missing, the previous layer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sources: Option<Vec<LayerSource>>,
+    /// geometry of a convolutional layer, whose neurons share the weights in 'kernel' and
+    /// have no weights of their own: if missing, the layer is not convolutional
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub convolution: Option<Convolution>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kernel: Vec<f64>,
     pub neurons: Vec<NeuronData>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct NeuronData {
+    /// weights of the synapses from each neuron of the previous layer (or of the sources of
+    /// the layer, one after the other): if missing, the neuron must be sparsely connected
+    /// or belong to a convolutional layer
+    #[serde(default)]
     pub weights: Vec<f64>,
+    /// synapses of a sparsely connected neuron, as (index of the source neuron in the
+    /// previous layer, weight) pairs: if missing, 'weights' is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synapses: Option<Vec<(usize, f64)>>,
     pub internal_weights: Vec<f64>,
+    /// bias added on every time step: if missing, the neuron has no bias
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bias: Option<f64>,
+    /// delays (in time steps) of the synapses: if missing, no synapse is delayed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub delays: Vec<usize>,
     pub v_th: f64,
     pub v_rest: f64,
     pub v_reset: f64,
     pub tau: f64,
+    /// model used by the neuron: if missing, the layer one is used
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<NeuronModel>,
+    /// firing activity measured for homeostasis during the current window, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homeostasis_state: Option<HomeostasisState>,
 }
 
 pub fn load_from_file(path: &str) -> Network {
@@ -38,9 +89,19 @@ pub fn load_from_file(path: &str) -> Network {
         nd.nr_outputs,
         nd.model,
     );
+    if let Some(noise) = nd.noise {
+        network.set_noise(noise);
+    }
 
     for layer_data in nd.layers {
-        let mut layer = Vec::<Neuron>::new();
+        let mut layer = Layer::new(Vec::new());
+        layer.model = layer_data
+            .model
+            .map(|model| Arc::new(model) as Arc<dyn NeuronDynamics>);
+        layer.reset_mode = layer_data.reset_mode;
+        layer.inhibition = layer_data.inhibition;
+        layer.homeostasis = layer_data.homeostasis;
+        layer.sources = layer_data.sources;
         for neuron_data in layer_data.neurons {
             let mut neuron = Neuron::new(
                 neuron_data.v_th,
@@ -48,9 +109,27 @@ pub fn load_from_file(path: &str) -> Network {
                 neuron_data.v_reset,
                 neuron_data.tau,
             );
-            neuron.set_weights(neuron_data.weights);
+            match neuron_data.synapses {
+                Some(synapses) => neuron.set_synapses(synapses),
+                None => {
+                    assert!(
+                        !neuron_data.weights.is_empty() || layer_data.convolution.is_some(),
+                        "Incorrect file format: a neuron has neither weights nor synapses"
+                    );
+                    neuron.set_weights(neuron_data.weights);
+                }
+            }
             neuron.set_internal_weights(neuron_data.internal_weights);
-            layer.push(neuron);
+            neuron.set_delays(neuron_data.delays);
+            neuron.set_bias(neuron_data.bias.unwrap_or(0.0));
+            neuron.model = neuron_data
+                .model
+                .map(|model| Arc::new(model) as Arc<dyn NeuronDynamics>);
+            neuron.homeostasis_state = neuron_data.homeostasis_state.unwrap_or_default();
+            layer.neurons.push(neuron);
+        }
+        if let Some(convolution) = layer_data.convolution {
+            layer.set_kernel(convolution, layer_data.kernel);
         }
         network.add_layer(layer);
     }
@@ -58,6 +137,140 @@ pub fn load_from_file(path: &str) -> Network {
     return network;
 }
 
+/// Export the network to a json file, which can be loaded again with load_from_file.
+/// The current value of each parameter is saved (e.g. thresholds adapted by homeostasis),
+/// together with the homeostasis state of the Neurons, so that it persists between runs.
+/// Only built-in neuron models can be exported: if the network, one of its layers or
+/// one of its Neurons uses a custom model, nothing is written and an error is returned.
+pub fn save_to_file(network: &Network, path: &str) -> Result<(), CustomModelError> {
+    let nd = NetworkData::try_from(network)?;
+    let json_str = serde_json::to_string(&nd).expect("Cannot serialize");
+    fs::write(path, json_str).expect("Couldn't write file");
+    Ok(())
+}
+
+/// Error returned when a network cannot be exported, because it uses a custom neuron
+/// model (see NeuronDynamics::builtin_model)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CustomModelError;
+
+impl fmt::Display for CustomModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Custom neuron models cannot be exported")
+    }
+}
+
+impl error::Error for CustomModelError {}
+
+impl TryFrom<&Network> for NetworkData {
+    type Error = CustomModelError;
+
+    fn try_from(network: &Network) -> Result<Self, Self::Error> {
+        Ok(NetworkData {
+            time_step_duration_us: network.time_step_duration_us,
+            nr_inputs: network.nr_inputs,
+            nr_outputs: network.nr_outputs,
+            model: export_model(network.model.as_ref())?,
+            noise: network.noise,
+            layers: network
+                .layers
+                .iter()
+                .map(LayerData::try_from)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl TryFrom<&Layer> for LayerData {
+    type Error = CustomModelError;
+
+    fn try_from(layer: &Layer) -> Result<Self, Self::Error> {
+        let mut neurons = layer
+            .neurons
+            .iter()
+            .map(NeuronData::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        // the weights shared by the neurons of a convolutional layer are exported once
+        if layer.kernel.is_some() {
+            for neuron in neurons.iter_mut() {
+                neuron.weights.clear();
+            }
+        }
+        // the homeostasis state is only meaningful if the layer regulates its thresholds
+        if layer.homeostasis.is_none() {
+            for neuron in neurons.iter_mut() {
+                neuron.homeostasis_state = None;
+            }
+        }
+        Ok(LayerData {
+            model: layer.model.as_deref().map(export_model).transpose()?,
+            reset_mode: layer.reset_mode,
+            inhibition: layer.inhibition,
+            homeostasis: layer.homeostasis,
+            sources: layer.sources.clone(),
+            convolution: layer.kernel.as_ref().map(|kernel| kernel.convolution),
+            kernel: layer
+                .kernel
+                .as_ref()
+                .map(|kernel| export_registers(&kernel.weights))
+                .unwrap_or_default(),
+            neurons,
+        })
+    }
+}
+
+impl TryFrom<&Neuron> for NeuronData {
+    type Error = CustomModelError;
+
+    fn try_from(neuron: &Neuron) -> Result<Self, Self::Error> {
+        let bias = neuron.bias.stored_value();
+        // the weights of a sparsely connected neuron are exported with their sources
+        let (weights, synapses) = match &neuron.synapse_sources {
+            Some(synapse_sources) => (
+                Vec::new(),
+                Some(
+                    synapse_sources
+                        .iter()
+                        .copied()
+                        .zip(export_registers(&neuron.weights))
+                        .collect(),
+                ),
+            ),
+            None => (export_registers(&neuron.weights), None),
+        };
+        Ok(NeuronData {
+            weights,
+            synapses,
+            internal_weights: export_registers(&neuron.internal_weights),
+            bias: (bias != 0.0).then_some(bias),
+            delays: neuron
+                .delays
+                .iter()
+                .map(|delay| delay.stored_value() as usize)
+                .collect(),
+            v_th: neuron.v_th.stored_value(),
+            v_rest: neuron.v_rest.stored_value(),
+            v_reset: neuron.v_reset.stored_value(),
+            tau: neuron.tau.stored_value(),
+            model: neuron.model.as_deref().map(export_model).transpose()?,
+            homeostasis_state: Some(neuron.homeostasis_state),
+        })
+    }
+}
+
+/// get the built-in model to be exported for a neuron model, if it is one
+fn export_model(model: &dyn NeuronDynamics) -> Result<NeuronModel, CustomModelError> {
+    model.builtin_model().ok_or(CustomModelError)
+}
+
+/// get the values stored in a Vec of Registers
+fn export_registers(registers: &[Register]) -> Vec<f64> {
+    registers
+        .iter()
+        .map(|register| register.stored_value())
+        .collect()
+}
+
 /// Serializable/Deserializable input matrix
 #[derive(Serialize, Deserialize)]
 pub struct InputMatrix(pub Vec<Vec<bool>>);
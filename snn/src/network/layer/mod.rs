@@ -0,0 +1,567 @@
+use crate::network::convolution::{Convolution, Kernel, ReceptiveField};
+use crate::network::dynamics::NeuronDynamics;
+use crate::network::neuron::Neuron;
+use crate::network::noise::NoiseSource;
+use crate::network::overlay;
+use crate::network::{
+    Fault, Homeostasis, LateralInhibition, LayerSource, NeuronParallelism, ResetMode,
+};
+use crate::register::Register;
+use std::sync::Arc;
+use std::thread;
+
+/// The Layer struct collects the Neurons belonging to the same layer of the
+/// network, together with the settings which are shared by all of them.
+///
+/// - model: neuron model used by all the Neurons of the layer which do not
+///   specify one of their own. If it is None, the model of the Network is used.
+/// - reset_mode: how the membrane potential of the Neurons is reset after firing.
+/// - inhibition: how the Neurons of the layer inhibit each other.
+/// - homeostasis: if set, how the thresholds of the Neurons are regulated.
+/// - parallelism: if set, how the Neurons are split among threads to be updated
+///   in parallel, when the layer is wide enough.
+/// - sources: if set, the entrances of the network and the earlier layers the layer
+///   receives pulses from, in order, together with the layers whose pulses are fed back
+///   to it (see LayerSource::Feedback). The synapses of its Neurons are indexed over all of
+///   them, one source after the other: e.g. with [Input, Layer(1)] synapse 0 comes from
+///   entrance 0 and synapse nr_inputs from Neuron 0 of layer 1. If it is None, the layer
+///   receives pulses from the previous layer only (from the entrances, if it is the first).
+/// - kernel: if set, the layer is convolutional and all its Neurons share the weights of
+///   the kernel (see Layer::set_kernel).
+///
+/// The layer also keeps track of the Neurons which emitted a pulse during the last
+/// time step it has been updated, which is needed to apply lateral inhibition.
+#[derive(Clone)]
+pub struct Layer {
+    pub neurons: Vec<Neuron>,                   // Neurons belonging to the layer
+    pub model: Option<Arc<dyn NeuronDynamics>>, // Model used by the layer neurons (overrides the network one)
+    pub reset_mode: ResetMode,                  // Reset mechanism applied after firing
+    pub inhibition: LateralInhibition,          // Lateral inhibition scheme
+    pub homeostasis: Option<Homeostasis>,       // Threshold regulation, if any
+    pub parallelism: Option<NeuronParallelism>, // Parallel update of the Neurons, if any
+    pub sources: Option<Vec<LayerSource>>, // Origins of the received pulses, if not the previous layer
+    pub kernel: Option<Kernel>,            // Weights shared by the Neurons, if convolutional
+    pub emitted_pulse_sources: Vec<usize>, // Neurons which fired during the last update
+}
+
+impl Layer {
+    /// Create a layer made up of the provided Neurons, which inherits the neuron
+    /// model from the network
+    pub fn new(neurons: Vec<Neuron>) -> Self {
+        Layer {
+            neurons,
+            model: None,
+            reset_mode: ResetMode::default(),
+            inhibition: LateralInhibition::default(),
+            homeostasis: None,
+            parallelism: None,
+            sources: None,
+            kernel: None,
+            emitted_pulse_sources: Vec::new(),
+        }
+    }
+
+    /// Create a layer made up of the provided Neurons, using the specified neuron
+    /// model for all Neurons which do not override it
+    pub fn with_model(neurons: Vec<Neuron>, model: impl NeuronDynamics + 'static) -> Self {
+        Layer {
+            neurons,
+            model: Some(Arc::new(model)),
+            reset_mode: ResetMode::default(),
+            inhibition: LateralInhibition::default(),
+            homeostasis: None,
+            parallelism: None,
+            sources: None,
+            kernel: None,
+            emitted_pulse_sources: Vec::new(),
+        }
+    }
+
+    /// Create a convolutional layer made up of the provided Neurons, which inherits the
+    /// neuron model from the network (see set_kernel)
+    pub fn convolutional(neurons: Vec<Neuron>, convolution: Convolution, kernel: Vec<f64>) -> Self {
+        let mut layer = Layer::new(neurons);
+        layer.set_kernel(convolution, kernel);
+        layer
+    }
+
+    /// Make the layer convolutional: its Neurons share the weights in 'kernel', which must
+    /// have Convolution::kernel_len elements, and each one only receives the pulses emitted
+    /// inside its own window. The layer must have Convolution::output_len Neurons, and any
+    /// weight previously set on them is discarded.
+    /// Modifying a weight of a single Neuron (see Neuron::register_mut) gives it its own
+    /// copy of the kernel, while a Fault on a kernel weight affects all of them.
+    ///
+    /// Panics if the geometry is not valid: the stride and the kernel size must be at least
+    /// 1 and the window must fit in the padded input. The number of sources of the pulses
+    /// received by the layer is checked against Convolution::input_len once the network
+    /// is run (see Topology::of).
+    pub fn set_kernel(&mut self, convolution: Convolution, kernel: Vec<f64>) {
+        assert!(
+            convolution.stride > 0,
+            "the window of a convolution must move by at least a position"
+        );
+        assert!(
+            convolution.kernel_size > 0,
+            "the window of a convolution must cover at least a position"
+        );
+        assert!(
+            convolution.kernel_size <= convolution.input_height + 2 * convolution.padding
+                && convolution.kernel_size <= convolution.input_width + 2 * convolution.padding,
+            "the window of a convolution must fit in the padded input"
+        );
+        assert_eq!(
+            kernel.len(),
+            convolution.kernel_len(),
+            "the kernel must have a weight for each position of each channel"
+        );
+        assert_eq!(
+            self.neurons.len(),
+            convolution.output_len(),
+            "a convolutional layer must have a Neuron for each output position"
+        );
+
+        let weights = Arc::new(kernel.into_iter().map(Register::new).collect::<Vec<_>>());
+        for (output_index, neuron) in self.neurons.iter_mut().enumerate() {
+            neuron.weights = weights.clone();
+            neuron.synapse_sources = None;
+            neuron.receptive_field = Some(ReceptiveField {
+                convolution,
+                output_index,
+            });
+        }
+        self.kernel = Some(Kernel {
+            convolution,
+            weights,
+        });
+    }
+
+    /// Get the number of Neurons in the layer
+    pub fn len(&self) -> usize {
+        self.neurons.len()
+    }
+
+    /// Check whether the layer contains no Neurons
+    pub fn is_empty(&self) -> bool {
+        self.neurons.is_empty()
+    }
+
+    /// Restore the state of the layer to the one it has before any simulation: the state
+    /// of each Neuron is reset (see Neuron::reset_state) and no Neuron has emitted pulses
+    pub fn reset_state(&mut self) {
+        for neuron in self.neurons.iter_mut() {
+            neuron.reset_state();
+        }
+        self.emitted_pulse_sources.clear();
+    }
+
+    /// Check whether the layer is in the same state as 'other' (see Neuron::has_same_state),
+    /// including the Neurons which emitted pulses during the last update
+    pub fn has_same_state(&self, other: &Layer) -> bool {
+        self.neurons.len() == other.neurons.len()
+            && self
+                .neurons
+                .iter()
+                .zip(&other.neurons)
+                .all(|(neuron, other_neuron)| neuron.has_same_state(other_neuron))
+            && self.emitted_pulse_sources == other.emitted_pulse_sources
+    }
+
+    /// Resolve the neuron model to be used by each Neuron of the layer: a model set on
+    /// the Neuron has priority over the one set on the layer, which in turn has priority
+    /// over the default model of the network ('network_model').
+    pub fn resolve_models(
+        &self,
+        network_model: &Arc<dyn NeuronDynamics>,
+    ) -> Vec<Arc<dyn NeuronDynamics>> {
+        let layer_model = self.model.as_ref().unwrap_or(network_model);
+        self.neurons
+            .iter()
+            .map(|neuron| neuron.model.as_ref().unwrap_or(layer_model).clone())
+            .collect()
+    }
+
+    /// Simulate the layer for a time step, given the sources of the Pulses received from
+    /// the sources of the layer during that time step (see Topology::gather):
+    /// - lateral inhibition due to pulses emitted by the layer during the previous time
+    ///   step is applied (except for time step 0)
+    /// - Pulses are delivered to each Neuron, taking into account synaptic delays
+    /// - the Neurons are updated and those which fire are collected
+    /// - thresholds are regulated, if the layer uses homeostasis.
+    ///
+    /// Neurons are updated ONLY if they receive some input, i.e. at least a pulse is delivered
+    /// to them or they have a bias (see Neuron::receives_input). The indexes of the Neurons
+    /// which fired are returned.
+    ///
+    /// 'faults' are the Faults affecting the layer (see FaultOverlay::of_layer): they are
+    /// applied to the Registers only while the layer is simulated.
+    pub fn step(
+        &mut self,
+        pulse_sources: &[usize],
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        noise: Option<&mut NoiseSource>,
+        faults: &[Fault],
+    ) -> Vec<usize> {
+        let armed_faults = overlay::arm(self, faults);
+        let emitted_pulse_sources = self.update(
+            pulse_sources,
+            time_step,
+            time_step_duration_ms,
+            neuron_models,
+            noise,
+        );
+        overlay::disarm(self, faults, armed_faults);
+
+        emitted_pulse_sources
+    }
+
+    /// Simulate for a time step only the Neurons with the provided indexes ('neuron_nrs',
+    /// in increasing order), as step does, and return the indexes of those which fired.
+    /// The other Neurons and the pulses emitted by the layer during the previous time
+    /// step are left untouched.
+    ///
+    /// This is possible only when the update of a Neuron does not depend on the other
+    /// Neurons of the layer during the same time step: the layer must not use noise, which
+    /// all the Neurons draw from in order, nor k-winner-take-all inhibition.
+    pub(crate) fn step_neurons(
+        &mut self,
+        neuron_nrs: &[usize],
+        pulse_sources: &[usize],
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        faults: &[Fault],
+    ) -> Vec<usize> {
+        assert!(
+            !matches!(self.inhibition, LateralInhibition::KWinnerTakeAll { .. }),
+            "Neurons competing with k-winner-take-all cannot be simulated on their own"
+        );
+
+        let armed_faults = overlay::arm(self, faults);
+        let mut emitted_pulse_sources = Vec::new();
+        for &i in neuron_nrs {
+            let neuron = &mut self.neurons[i];
+            let model = neuron_models[i].as_ref();
+            if time_step > 0 {
+                Self::inhibit_neuron(
+                    self.inhibition,
+                    &self.emitted_pulse_sources,
+                    i,
+                    neuron,
+                    time_step,
+                    time_step_duration_ms,
+                    model,
+                    None,
+                );
+            }
+
+            let due_pulse_sources = neuron.receive_pulses(pulse_sources, time_step);
+            let fired = neuron.receives_input(&due_pulse_sources, time_step)
+                && neuron.feed_pulses(
+                    &due_pulse_sources,
+                    time_step,
+                    time_step_duration_ms,
+                    model,
+                    None,
+                    self.reset_mode,
+                );
+            if fired {
+                emitted_pulse_sources.push(i);
+            }
+            if let Some(homeostasis) = self.homeostasis {
+                neuron.regulate_threshold(fired, &homeostasis, time_step);
+            }
+        }
+        overlay::disarm(self, faults, armed_faults);
+
+        emitted_pulse_sources
+    }
+
+    /// Simulate the layer for a time step (see step), with the Faults already applied
+    fn update(
+        &mut self,
+        pulse_sources: &[usize],
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        mut noise: Option<&mut NoiseSource>,
+    ) -> Vec<usize> {
+        // apply inhibitive contribution due to pulses generated by nodes of the current layer
+        // during previous time step (except for time step 0)
+        if time_step > 0 {
+            self.inhibit(
+                time_step,
+                time_step_duration_ms,
+                neuron_models,
+                noise.as_deref_mut(),
+            );
+        }
+        // all neurons updated: clear emitted_pulse_sources Vec so that it can
+        // be used again, for next iteration
+        self.emitted_pulse_sources.clear();
+
+        // Pulses each Neuron has to process during the current time step: they
+        // may differ from one Neuron to the other due to synaptic delays
+        let due_pulse_sources = self
+            .neurons
+            .iter_mut()
+            .map(|neuron| neuron.receive_pulses(pulse_sources, time_step))
+            .collect::<Vec<Vec<usize>>>();
+
+        // Update the status for the layer Neurons ONLY if at least one of them
+        // receives some input, otherwise there is no need to do that.
+        if self
+            .neurons
+            .iter()
+            .zip(&due_pulse_sources)
+            .all(|(neuron, sources)| !neuron.receives_input(sources, time_step))
+        {
+            // no Neuron fires, but the time step still counts for homeostasis
+            self.regulate_thresholds(time_step);
+            return Vec::new();
+        }
+
+        match self.inhibition {
+            LateralInhibition::KWinnerTakeAll { k } => self.feed_k_winners(
+                &due_pulse_sources,
+                k,
+                time_step,
+                time_step_duration_ms,
+                neuron_models,
+                noise,
+            ),
+            LateralInhibition::Dense | LateralInhibition::Uniform { .. } => {
+                // Feed Pulses to all neurons in the layer which receive some input, keeping
+                // track of the neurons which 'fire'
+                let reset_mode = self.reset_mode;
+                self.emitted_pulse_sources = Self::update_neurons(
+                    &mut self.neurons,
+                    neuron_models,
+                    self.parallelism,
+                    noise,
+                    |i, neuron, model, noise| {
+                        neuron.receives_input(&due_pulse_sources[i], time_step)
+                            && neuron.feed_pulses(
+                                &due_pulse_sources[i],
+                                time_step,
+                                time_step_duration_ms,
+                                model,
+                                noise,
+                                reset_mode,
+                            )
+                    },
+                );
+            }
+        }
+
+        self.regulate_thresholds(time_step);
+
+        self.emitted_pulse_sources.clone()
+    }
+
+    /// let each Neuron regulate its threshold according to whether it fired during the
+    /// current time step, if the layer uses homeostasis
+    fn regulate_thresholds(&mut self, time_step: usize) {
+        if let Some(homeostasis) = self.homeostasis {
+            for (i, neuron) in self.neurons.iter_mut().enumerate() {
+                let fired = self.emitted_pulse_sources.contains(&i);
+                neuron.regulate_threshold(fired, &homeostasis, time_step);
+            }
+        }
+    }
+
+    /// apply the inhibitive contribution due to the pulses emitted by the layer during
+    /// the previous time step, according to the inhibition scheme of the layer
+    fn inhibit(
+        &mut self,
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        noise: Option<&mut NoiseSource>,
+    ) {
+        // competition is resolved while firing, there is no inhibition afterwards
+        if let LateralInhibition::KWinnerTakeAll { .. } = self.inhibition {
+            return;
+        }
+        let inhibition = self.inhibition;
+        let emitted_pulse_sources = &self.emitted_pulse_sources;
+        Self::update_neurons(
+            &mut self.neurons,
+            neuron_models,
+            self.parallelism,
+            noise,
+            |i, neuron, model, noise| {
+                Self::inhibit_neuron(
+                    inhibition,
+                    emitted_pulse_sources,
+                    i,
+                    neuron,
+                    time_step,
+                    time_step_duration_ms,
+                    model,
+                    noise,
+                );
+                false
+            },
+        );
+    }
+
+    /// apply to the Neuron with index 'i' the inhibitive contribution due to the pulses
+    /// emitted by the layer during the previous time step ('emitted_pulse_sources')
+    #[allow(clippy::too_many_arguments)]
+    fn inhibit_neuron(
+        inhibition: LateralInhibition,
+        emitted_pulse_sources: &Vec<usize>,
+        i: usize,
+        neuron: &mut Neuron,
+        time_step: usize,
+        time_step_duration_ms: f64,
+        model: &dyn NeuronDynamics,
+        noise: Option<&mut NoiseSource>,
+    ) {
+        match inhibition {
+            LateralInhibition::Dense => neuron.inhibite_after_pulses_emission(
+                emitted_pulse_sources,
+                time_step,
+                time_step_duration_ms,
+                model,
+                noise,
+            ),
+            LateralInhibition::Uniform { strength } => {
+                // a Neuron is not inhibited by its own pulses
+                let other_pulse_sources = emitted_pulse_sources
+                    .iter()
+                    .copied()
+                    .filter(|source| *source != i)
+                    .collect::<Vec<usize>>();
+                neuron.inhibite_uniformly_after_pulses_emission(
+                    &other_pulse_sources,
+                    strength,
+                    time_step,
+                    time_step_duration_ms,
+                    model,
+                    noise,
+                );
+            }
+            LateralInhibition::KWinnerTakeAll { .. } => {}
+        }
+    }
+
+    /// Apply 'update' to each Neuron of the layer, given its index and model, and return the
+    /// indexes of the Neurons for which it returned true, in increasing order.
+    ///
+    /// If 'parallelism' is set and the layer has at least parallelism.min_neurons Neurons,
+    /// the Neurons are split in chunks which are updated by different threads. This is not
+    /// done when a NoiseSource is provided: all the Neurons draw from the same sequence of
+    /// random values, so they must be updated in order for results to be reproducible.
+    fn update_neurons<F>(
+        neurons: &mut [Neuron],
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        parallelism: Option<NeuronParallelism>,
+        mut noise: Option<&mut NoiseSource>,
+        update: F,
+    ) -> Vec<usize>
+    where
+        F: Fn(usize, &mut Neuron, &dyn NeuronDynamics, Option<&mut NoiseSource>) -> bool + Sync,
+    {
+        let parallelism = parallelism.filter(|parallelism| {
+            parallelism.threads > 1 && neurons.len() >= parallelism.min_neurons && noise.is_none()
+        });
+
+        let Some(parallelism) = parallelism else {
+            return neurons
+                .iter_mut()
+                .zip(neuron_models)
+                .enumerate()
+                .filter_map(|(i, (neuron, model))| {
+                    update(i, neuron, model.as_ref(), noise.as_deref_mut()).then_some(i)
+                })
+                .collect();
+        };
+
+        let chunk_size = neurons.len().div_ceil(parallelism.threads);
+        let update = &update;
+        thread::scope(|scope| {
+            let handles = neurons
+                .chunks_mut(chunk_size)
+                .zip(neuron_models.chunks(chunk_size))
+                .enumerate()
+                .map(|(chunk_nr, (neurons, models))| {
+                    scope.spawn(move || {
+                        neurons
+                            .iter_mut()
+                            .zip(models)
+                            .enumerate()
+                            .filter_map(|(j, (neuron, model))| {
+                                let i = chunk_nr * chunk_size + j;
+                                update(i, neuron, model.as_ref(), None).then_some(i)
+                            })
+                            .collect::<Vec<usize>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            // chunks are joined in order, so that indexes are sorted
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect()
+        })
+    }
+
+    /// Feed Pulses to all neurons in the layer which receive some input, then allow only the
+    /// k Neurons having the highest membrane potential, among those which would fire, to
+    /// actually fire (and be reset)
+    fn feed_k_winners(
+        &mut self,
+        due_pulse_sources: &[Vec<usize>],
+        k: usize,
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        mut noise: Option<&mut NoiseSource>,
+    ) {
+        // integrate pulses and collect the candidates to fire
+        let mut candidates = Vec::new();
+        for (i, neuron) in self.neurons.iter_mut().enumerate() {
+            if !neuron.receives_input(&due_pulse_sources[i], time_step) {
+                continue;
+            }
+            neuron.integrate_pulses(
+                &due_pulse_sources[i],
+                time_step,
+                time_step_duration_ms,
+                neuron_models[i].as_ref(),
+                noise.as_deref_mut(),
+            );
+            if neuron.fires(time_step, neuron_models[i].as_ref(), noise.as_deref_mut()) {
+                let v_mem = neuron.v_mem.read_value(Some(time_step)).unwrap();
+                candidates.push((i, v_mem));
+            }
+        }
+
+        // the k candidates with the highest membrane potential win (ties are resolved
+        // in favour of the Neuron with the lowest index)
+        candidates.sort_by(|(_, v1), (_, v2)| v2.total_cmp(v1));
+        candidates.truncate(k);
+        candidates.sort_by_key(|(i, _)| *i);
+
+        // winners fire: their membrane potential must be reset
+        for (i, _) in candidates {
+            neuron_models[i].reset(&mut self.neurons[i], self.reset_mode, time_step);
+            self.emitted_pulse_sources.push(i);
+        }
+    }
+}
+
+impl From<Vec<Neuron>> for Layer {
+    fn from(neurons: Vec<Neuron>) -> Self {
+        Layer::new(neurons)
+    }
+}
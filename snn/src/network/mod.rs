@@ -1,23 +1,80 @@
 use rand::Rng;
 use rand::{seq::SliceRandom, thread_rng};
 
-use crate::network::neuron::{Message, Neuron};
-use crate::register::Damage;
+use crate::network::concurrent::ConcurrentRun;
+use crate::network::dynamics::NeuronDynamics;
+use crate::network::layer::Layer;
+use crate::network::neuron::{Message, NeuronRegister};
+use crate::network::noise::{NoiseModel, NoiseSource};
+use crate::network::overlay::FaultOverlay;
+use crate::network::packed::PackedLayer;
+use crate::network::probe::{Probes, Trace, TracedRun};
+use crate::network::session::{Checkpoint, Session};
+use crate::network::topology::Topology;
+use crate::network::worker::{LayerRun, LayerRunResult, PoolSlot};
+use crate::register::{Damage, Register};
 use serde::{Deserialize, Serialize};
-use std::sync::mpsc::{self, Receiver};
-use std::thread::{self, JoinHandle};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle, ThreadId};
 
+pub mod concurrent;
+pub mod convolution;
+pub mod dynamics;
 pub mod json;
+pub mod layer;
 pub mod neuron;
+pub mod noise;
+pub mod overlay;
+pub mod packed;
+pub mod probe;
+pub mod session;
+pub mod topology;
+pub mod worker;
 
 /// Struct to describe damage in detail
 #[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct DamageDetail {
-    at_iteration: usize,
-    damage_type: FaultyElement,
-    at_layer: usize,
-    at_neuron: usize,
-    at_bit: usize,
+    pub at_iteration: usize,
+    pub damage_type: FaultyElement,
+    pub at_layer: usize,
+    pub at_neuron: usize,
+    // damaged Register of the Neuron, None if the Neuron has no such element
+    pub at_register: Option<NeuronRegister>,
+    pub at_bit: usize,
+}
+
+/// Address of a Register inside the network: the layer and the Neuron it belongs to,
+/// and which Register of the Neuron it is
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RegisterAddress {
+    pub layer: usize,
+    pub neuron: usize,
+    pub register: NeuronRegister,
+}
+
+/// A Fault is a Damage applied to a specific Register of the network
+#[derive(Clone, Copy)]
+pub struct Fault {
+    pub address: RegisterAddress,
+    pub damage: Damage,
+}
+
+/// Result of a run without damages performed with run_with_checkpoints: the output of the
+/// network and the Checkpoint of its state at the beginning of each time step
+/// (checkpoints[j] is taken before the time step of output column 'j' is simulated)
+pub struct GoldenRun {
+    pub output: Vec<Vec<bool>>,
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+/// Result of a run with a fault performed with resume_with_fault: the output of the network
+/// and, if the run has been stopped early because the fault has been masked, the output
+/// column of the time step at the end of which the state of the network converged back to
+/// the one without damages
+pub struct FaultyRun {
+    pub output: Vec<Vec<bool>>,
+    pub masked_at: Option<usize>,
 }
 
 /// Struct to hold the simulation result
@@ -54,6 +111,10 @@ pub struct SimulationResult {
     pub type_of_damage: DamageModel,
     pub output_without_damages: Vec<Vec<bool>>,
     pub diffs: Vec<Vec<SimulationResultCell>>,
+    // number of faulty runs stopped early because the fault has been masked, if early
+    // termination has been enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub masked_faults: Option<usize>,
 }
 
 impl SimulationResult {
@@ -79,6 +140,11 @@ impl SimulationResult {
             }
             println!("");
         }
+
+        if let Some(masked_faults) = self.masked_faults {
+            println!("\n");
+            println!("Number of faults masked before the end of the simulation: {masked_faults}");
+        }
     }
 }
 
@@ -95,6 +161,8 @@ pub enum FaultyElement {
     Adder,
     Multiplier,
     Divider,
+    Delays,
+    Biases,
 }
 
 /// enum DamageModel is used to specify what kind of damage to the network elements
@@ -106,27 +174,159 @@ pub enum DamageModel {
     TransientBitFlip,
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+/// enum NeuronModel lists the built-in neuron models, i.e. those which can be
+/// selected from the json description of the network. Custom models can be
+/// provided implementing the NeuronDynamics trait.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum NeuronModel {
     LeakyIntegrateAndFire,
     IntegrateAndFire,
 }
 
+/// enum ResetMode lists the ways the membrane potential of a Neuron can be reset
+/// after the Neuron fires
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ResetMode {
+    /// v_mem is set to v_reset
+    #[default]
+    ToResetPotential,
+    /// v_th is subtracted from v_mem
+    BySubtraction,
+    /// v_mem is left unchanged
+    NoReset,
+}
+
+/// enum LateralInhibition lists the schemes a layer can use to make its Neurons
+/// compete with each other
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum LateralInhibition {
+    /// pulses emitted by a Neuron inhibit the other Neurons of the layer at the
+    /// following time step, according to their internal_weights
+    #[default]
+    Dense,
+    /// pulses emitted by a Neuron inhibit all the other Neurons of the layer at the
+    /// following time step, by the same strength (mV)
+    Uniform { strength: f64 },
+    /// at each time step, only the k Neurons having the highest membrane potential
+    /// among those reaching the threshold are allowed to fire
+    KWinnerTakeAll { k: usize },
+}
+
+/// enum LayerSource lists the origins of the pulses a layer can receive (see Layer::sources)
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum LayerSource {
+    /// the entrances of the network
+    Input,
+    /// the Neurons of an earlier layer of the network, by index
+    Layer(usize),
+    /// the Neurons of any layer of the network, by index, whose pulses are fed back with a
+    /// delay of one time step: those emitted at time step 't' are received at time step
+    /// 't + 1', so that recurrent networks can be modeled
+    Feedback(usize),
+}
+
+/// The struct NeuronParallelism describes how the Neurons of a wide layer are updated
+/// in parallel, when pulses are fed to them and when lateral inhibition is applied:
+///
+/// - min_neurons: minimum number of Neurons a layer must have to be updated in parallel,
+///   since for narrower layers spawning threads costs more than it saves
+/// - threads: number of threads the Neurons of the layer are split among
+///
+/// Layers affected by noise are always updated by a single thread (see NoiseSource).
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NeuronParallelism {
+    pub min_neurons: usize,
+    pub threads: usize,
+}
+
+/// enum Engine lists the ways a Network can be simulated. All engines produce the same
+/// results; they differ in how the computation is scheduled.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum Engine {
+    /// each layer is run in its own thread and layers exchange pulses through channels
+    #[default]
+    Threaded,
+    /// all layers are run one after the other in the calling thread, one time step at a
+    /// time: it avoids the cost of spawning threads and its scheduling is deterministic,
+    /// which makes it faster for small networks and easier to debug
+    Sequential,
+    /// each layer is run by a worker thread which is kept alive across runs (see
+    /// WorkerPool): the workers are spawned by the first run of the network and reused by
+    /// the following ones, e.g. by all the runs of a fault injection campaign or a batch
+    Pipelined,
+    /// during a fault injection campaign, all the faulty runs are simulated together with
+    /// the run without damages, in a single pass (see concurrent::run). Runs without
+    /// faults are performed like with the sequential engine
+    Concurrent,
+    /// like the sequential engine, but the layers are converted to PackedLayers at the
+    /// beginning of each run, so that their Neurons are updated on contiguous arrays: it is
+    /// faster for wide layers (see PackedLayer). The PackedLayers are kept by the network
+    /// and reused by the following runs, as long as the synapses of the layers are unchanged
+    Packed,
+}
+
+/// The struct Homeostasis describes how the thresholds of the Neurons of a layer
+/// are regulated, so that their firing rate moves towards a target:
+///
+/// - target_rate: desired firing rate (pulses per time step, between 0.0 and 1.0)
+/// - window: number of time steps over which the firing rate is measured
+/// - learning_rate: change of v_th (mV) per unit of difference between the measured
+///   firing rate and the target one. At the end of each window v_th is raised if the
+///   Neuron fired too often, lowered otherwise.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Homeostasis {
+    pub target_rate: f64,
+    pub window: usize,
+    pub learning_rate: f64,
+}
+
 /// The struct Network represents a Spiking Neural Network.
-/// All the Neurons inside the network belong to a layer and
-/// each layer collects its own Neurons inside a Vec. The set
-/// of all Vec representing layers is then collected inside an
-/// outer Vec.
+/// All the Neurons inside the network belong to a Layer and
+/// each Layer collects its own Neurons inside a Vec. The set
+/// of all Layers is then collected inside a Vec. Each Layer receives the pulses
+/// emitted by the previous one, unless it specifies its own sources among the
+/// entrances of the network and the earlier layers, possibly together with the pulses
+/// fed back by any layer at the previous time step (see Layer::sources); the last
+/// Layer produces the output of the network. A Layer can be convolutional, its Neurons
+/// sharing the weights of a kernel slid over its sources (see Layer::set_kernel).
 ///
 /// The Network evolves temporally in discrete time steps, whose
 /// length can be set at network creation (expressed in microseconds).
+///
+/// The neuron model set at network creation is the default one: each
+/// Layer, and each Neuron inside it, can override it with its own model.
+/// Any type implementing the NeuronDynamics trait can be used as a model.
+///
+/// Optionally, a NoiseModel can be set to simulate the intrinsic noise of the
+/// Neurons (membrane noise and stochastic firing).
+///
+/// The Engine used to simulate the network can be chosen at runtime (threaded by default).
+/// When checkpointing is enabled, fault injection campaigns save the state of the network
+/// at each time step of the run without damages, so that runs with a transient fault can
+/// start from the time step of the fault instead of time step 0. When early termination is
+/// enabled, faulty runs are also stopped as soon as the fault is masked (see
+/// resume_with_fault), which requires checkpoints as well. In both cases the runs of the
+/// campaign are simulated one time step at a time through a Session, so campaigns refuse
+/// them unless the Engine of the network is the sequential one, or the concurrent one,
+/// which stops masked runs on its own.
+///
+/// Damages are applied through a FaultOverlay, which the engines consult while simulating
+/// each layer: the Registers of the network always keep the values without damages.
 #[derive(Clone)]
 pub struct Network {
     pub nr_inputs: usize,
     pub nr_outputs: usize,
-    pub time_step_duration_us: f64, // Time step duration
-    pub layers: Vec<Vec<Neuron>>,   // Vec collecting layers (other Vecs)
-    pub model: NeuronModel,         // Model used by neurons (e.g. LIF, IF)
+    pub time_step_duration_us: f64,     // Time step duration
+    pub layers: Vec<Layer>,             // Vec collecting layers
+    pub model: Arc<dyn NeuronDynamics>, // Default model used by neurons (e.g. LIF, IF)
+    pub noise: Option<NoiseModel>,      // Noise affecting neurons, if any
+    pub engine: Engine,                 // Engine used to simulate the network
+    pub checkpointing: bool,            // Whether campaigns restart faulty runs from checkpoints
+    pub early_termination: bool,        // Whether campaigns stop faulty runs once faults are masked
+    pub faults: FaultOverlay,           // Damages applied to the Registers of the network
+    time_step: usize,                   // next time step to be simulated, since the last reset
+    packed_layers: Vec<PackedLayer>,    // layers packed by the last run of the packed engine
+    worker_pool: PoolSlot,              // layer workers kept by the pipelined engine
 }
 
 impl Network {
@@ -135,22 +335,119 @@ impl Network {
         time_step_duration_us: f64,
         nr_inputs: usize,
         nr_outputs: usize,
-        model: NeuronModel,
+        model: impl NeuronDynamics + 'static,
     ) -> Self {
         Network {
             nr_inputs,
             nr_outputs,
             time_step_duration_us,
             layers: Vec::new(),
-            model,
+            model: Arc::new(model),
+            noise: None,
+            engine: Engine::default(),
+            checkpointing: false,
+            early_termination: false,
+            faults: FaultOverlay::new(),
+            time_step: 0,
+            packed_layers: Vec::new(),
+            worker_pool: PoolSlot::default(),
         }
     }
 
-    /// Add a layer to the network
-    pub fn add_layer(&mut self, layer: Vec<Neuron>) {
+    /// Set the noise model used by all Neurons of the network.
+    ///
+    /// Panics if the noise model is not valid (see NoiseModel::assert_valid).
+    pub fn set_noise(&mut self, noise: NoiseModel) {
+        noise.assert_valid();
+        self.noise = Some(noise);
+    }
+
+    /// Set the Engine used to simulate the network
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    /// Get the ids of the threads of the layer workers kept by the network for the
+    /// pipelined engine (see Engine::Pipelined), which is empty before its first run
+    pub fn worker_threads(&self) -> Vec<ThreadId> {
+        self.worker_pool.thread_ids()
+    }
+
+    /// Enable or disable checkpointing during fault injection campaigns (see
+    /// run_with_checkpoints). It requires the sequential or the concurrent engine.
+    pub fn set_checkpointing(&mut self, checkpointing: bool) {
+        self.checkpointing = checkpointing;
+    }
+
+    /// Enable or disable early termination of faulty runs during fault injection campaigns.
+    /// As with checkpointing, it requires the sequential or the concurrent engine.
+    pub fn set_early_termination(&mut self, early_termination: bool) {
+        self.early_termination = early_termination;
+    }
+
+    /// Set how the Neurons of all the layers of the network are updated in parallel
+    /// (None to update them with a single thread). Layers added afterwards are not affected.
+    pub fn set_neuron_parallelism(&mut self, parallelism: Option<NeuronParallelism>) {
+        for layer in self.layers.iter_mut() {
+            layer.parallelism = parallelism;
+        }
+    }
+
+    /// Add a layer to the network. Either a Layer or a plain Vec of Neurons can be
+    /// provided: in the latter case the layer uses the default model of the network.
+    ///
+    /// Panics if the layer receives pulses, without feedback, from a layer which is not an
+    /// earlier one, or from the same source more than once (see Layer::sources). Layers
+    /// providing feedback may be added later.
+    pub fn add_layer(&mut self, layer: impl Into<Layer>) {
+        let layer = layer.into();
+        if let Some(sources) = &layer.sources {
+            assert!(!sources.is_empty(), "a layer must have at least a source");
+            for (i, source) in sources.iter().enumerate() {
+                if let LayerSource::Layer(source_layer) = source {
+                    assert!(
+                        *source_layer < self.layers.len(),
+                        "a layer can only receive pulses from earlier layers"
+                    );
+                }
+                assert!(
+                    !sources[..i].contains(source),
+                    "a layer cannot receive pulses from the same source more than once"
+                );
+            }
+        }
         self.layers.push(layer);
     }
 
+    /// Get the sources of the pulses received by a layer (see Layer::sources): a layer
+    /// which does not specify them receives the pulses emitted by the previous layer, or
+    /// those received by the entrances of the network if it is the first one
+    pub fn sources_of(&self, layer_nr: usize) -> Vec<LayerSource> {
+        match &self.layers[layer_nr].sources {
+            Some(sources) => sources.clone(),
+            None if layer_nr == 0 => vec![LayerSource::Input],
+            None => vec![LayerSource::Layer(layer_nr - 1)],
+        }
+    }
+
+    /// Get the number of pulse sources of a LayerSource, i.e. the number of entrances of
+    /// the network or the number of Neurons of the layer
+    pub fn source_len(&self, source: LayerSource) -> usize {
+        match source {
+            LayerSource::Input => self.nr_inputs,
+            LayerSource::Layer(layer_nr) | LayerSource::Feedback(layer_nr) => {
+                self.layers[layer_nr].len()
+            }
+        }
+    }
+
+    /// Open a Session to simulate the network one time step at a time. The Session
+    /// starts from the current state of the network: call reset_state before opening
+    /// it to start from the initial state.
+    pub fn open_session(&mut self) -> Session<'_> {
+        Session::new(self)
+    }
+
     /// Get output nodes number
     pub fn get_outputs_number(&self) -> Result<usize, ()> {
         if self.layers.len() == 0 {
@@ -171,26 +468,102 @@ impl Network {
     /// its output channel and write the result of the SNN computation inside the 'output'
     /// boolean matrix, having dimension (nr. output neurons) x (nr. time steps).
     /// Each row corresponds to a particular output neuron, by index, while each column
-    /// correponds to a certain time step. If output[i][j] == true, it means that, at time
-    /// step 'j', the output neuron 'i' produced a pulse (false means no pulse, instead).
-    pub fn write_results(rx: Receiver<Message>, output: &mut Vec<Vec<bool>>) {
-        let mut time_step = 0;
-
-        // read all Messages from the receiver side of the output channel
-        while let Ok(message) = rx.recv() {
-            match message {
-                // if the message is GoAhead, that means that all the pulses for the current
-                // time step have been read, and so time step can be incremented (i.e. it is
-                // possible to start filling the following column in the output matrix)
-                Message::GoAhead => {
-                    time_step += 1;
+    /// correponds to a certain time step of the run, the first one being 'first_time_step'
+    /// (see Network::time_step). If output[i][j] == true, it means that, at time
+    /// step 'first_time_step + j', the output neuron 'i' produced a pulse (false means no
+    /// pulse, instead).
+    ///
+    /// Panics if the Messages are not ordered by time step, or if the end-of-step marker
+    /// (GoAhead) is missing for some time step.
+    pub fn write_results(rx: &Receiver<Message>, first_time_step: usize, output: &mut [Vec<bool>]) {
+        let time_steps_number = output.first().map_or(0, |row| row.len());
+
+        // read all Messages for each time step from the receiver side of the output channel:
+        // pulses are written in the column corresponding to the time step they belong to
+        (0..time_steps_number).for_each(|column| {
+            let time_step = first_time_step + column;
+            let pulse_sources = Self::receive_time_step_pulses(rx, time_step, "output")
+                .unwrap_or_else(|| {
+                    panic!(
+                        "output is processing time step {time_step} but the sender \
+                        disconnected before the end of the time step"
+                    )
+                });
+            for source_index in pulse_sources {
+                output[source_index][column] = true;
+            }
+        });
+    }
+
+    /// Send the Pulses contained in the input matrix over the channels feeding the layers
+    /// which receive them, ordered by time step: all Pulses of a time step are followed by
+    /// a GoAhead Message. The first column of the matrix is sent as 'first_time_step'.
+    fn inject_input(senders: &[Sender<Message>], input: &[Vec<bool>], first_time_step: usize) {
+        for sender in senders {
+            Self::inject_input_into(sender, input, first_time_step);
+        }
+    }
+
+    /// Send the Pulses contained in the input matrix over a single channel (see inject_input)
+    fn inject_input_into(sender: &Sender<Message>, input: &[Vec<bool>], first_time_step: usize) {
+        for column in 0..input[0].len() {
+            let time_step = first_time_step + column;
+            for (input_node, entrance) in input.iter().enumerate() {
+                if entrance[column] {
+                    sender
+                        .send(Message::Pulse {
+                            time_step,
+                            source_index: input_node,
+                        })
+                        .unwrap();
+                }
+            }
+            sender.send(Message::GoAhead { time_step }).unwrap();
+        }
+    }
+
+    /// Receive all the Pulses belonging to the specified time step from a channel, until the
+    /// GoAhead Message closing that time step is received, and return their sources.
+    /// Each Message is checked to belong to the expected time step: a mismatch means that
+    /// the sender and the receiver are out of sync, so the function panics instead of
+    /// producing misaligned results. 'receiver_name' is used to report the error.
+    /// Returns None if the sender disconnected before the end of the time step.
+    fn receive_time_step_pulses(
+        receiver: &Receiver<Message>,
+        time_step: usize,
+        receiver_name: &str,
+    ) -> Option<Vec<usize>> {
+        let mut pulse_sources = Vec::new();
+        loop {
+            match receiver.recv() {
+                // a pulse for the current time step: keep track of its source
+                Ok(Message::Pulse {
+                    time_step: pulse_time_step,
+                    source_index,
+                }) if pulse_time_step == time_step => {
+                    pulse_sources.push(source_index);
                 }
-                // if the message is a Pulse, then it contains the index of the neuron
-                // of the previous layer which produced the pulse itself, which also matches
-                // the index of the row in the output matrix to fill
-                Message::Pulse(source_index) => {
-                    output[source_index][time_step] = true;
+                // all pulses for the current time step have been received
+                Ok(Message::GoAhead {
+                    time_step: go_ahead_time_step,
+                }) if go_ahead_time_step == time_step => {
+                    return Some(pulse_sources);
                 }
+                // a Message belonging to another time step
+                Ok(Message::Pulse {
+                    time_step: received_time_step,
+                    ..
+                })
+                | Ok(Message::GoAhead {
+                    time_step: received_time_step,
+                }) => {
+                    panic!(
+                        "{receiver_name} is processing time step {time_step} but received a \
+                        message for time step {received_time_step}"
+                    );
+                }
+                // the sender terminated without closing the time step
+                Err(_) => return None,
             }
         }
     }
@@ -213,20 +586,105 @@ impl Network {
     /// entrance of the SNN, and each column corresponds to a certain time step.
     /// If input[i][j] == true, it means that, at time step 'j', the SNN receives a pulse on
     /// the entrance 'i'. Otherwise, if it false, no input is received for that time step.
+    ///
+    /// Panics if the threads simulating the network fall out of sync, i.e. if a layer or
+    /// the output collector receives a Message belonging to a time step other than the one
+    /// it is processing (see write_results). A panic raised while simulating a layer in
+    /// another thread is propagated to the caller.
     pub fn run(mut self, input: Vec<Vec<bool>>) -> Vec<Vec<bool>> {
-        // Number of entrances of SNN, equal to the number of rows of the 'input' matrix
-        let snn_inputs_number = input.len();
+        self.run_in_place(&input)
+    }
+
+    /// Simulate the network like 'run', without consuming it: at the end of the simulation
+    /// each Neuron keeps its state (e.g. membrane potential and thresholds adapted by
+    /// homeostasis), so that the network can be run again, continuing from that state, or
+    /// exported. Call reset_state before running to start from the initial state instead.
+    ///
+    /// The time steps of a run follow those simulated by the previous ones (see time_step),
+    /// so that running the network on two consecutive parts of an input gives the same
+    /// output as running it on the whole input. Only the noise, if any, is drawn again
+    /// from the beginning of its sequence at every run.
+    ///
+    /// Panics in the same cases as 'run'.
+    pub fn run_in_place(&mut self, input: &[Vec<bool>]) -> Vec<Vec<bool>> {
+        self.run_with_probes(input, &Probes::new()).output
+    }
+
+    /// Simulate the network on each of the provided inputs (see run), returning the output
+    /// matrix of each one, in the same order. Every sample starts from the initial state of
+    /// the network (see reset_state), with the thresholds it has before the batch, so that
+    /// outputs do not depend on the order of the samples. Damages applied to the network
+    /// affect all the samples.
+    ///
+    /// The samples are split among 'threads' threads, each one simulating its own copy of
+    /// the network with the Engine of the network. If 'threads' is 0 or 1, they are all
+    /// simulated by the calling thread. The network itself is left unchanged.
+    pub fn run_batch(&self, inputs: &[Vec<Vec<bool>>], threads: usize) -> Vec<Vec<Vec<bool>>> {
+        // the same copy of the network simulates all the samples of a chunk
+        let run_samples = |samples: &[Vec<Vec<bool>>]| {
+            let mut snn = self.clone();
+            samples
+                .iter()
+                .map(|input| {
+                    snn.reset_state();
+                    snn.restore_homeostasis_from(self);
+                    snn.run_in_place(input)
+                })
+                .collect::<Vec<Vec<Vec<bool>>>>()
+        };
+
+        if threads <= 1 || inputs.len() <= 1 {
+            return run_samples(inputs);
+        }
+
+        let chunk_size = inputs.len().div_ceil(threads);
+        let run_samples = &run_samples;
+        thread::scope(|scope| {
+            let handles = inputs
+                .chunks(chunk_size)
+                .map(|samples| scope.spawn(move || run_samples(samples)))
+                .collect::<Vec<_>>();
+
+            // chunks are joined in order, so that outputs follow the order of the inputs
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect()
+        })
+    }
+
+    /// Simulate the network like 'run_in_place', recording the values of the probed
+    /// Registers at the end of every time step. Damages applied to the network affect
+    /// the recorded values, so the function can be used to trace both runs without
+    /// damages and runs with damages.
+    ///
+    /// Panics in the same cases as 'run'.
+    pub fn run_with_probes(&mut self, input: &[Vec<bool>], probes: &Probes) -> TracedRun {
+        match self.engine {
+            Engine::Threaded => self.run_threaded(input, probes),
+            Engine::Sequential | Engine::Concurrent => self.run_sequentially(input, probes),
+            Engine::Pipelined => {
+                // the workers spawned by the first run are kept for the following ones. If
+                // the run panics the pool is dropped, so the next run spawns new workers
+                let pool = self.worker_pool.take(self.layers.len());
+                let traced_run = pool.run(self, input, probes);
+                self.worker_pool.put(pool);
+                traced_run
+            }
+            Engine::Packed => self.run_packed(input, probes),
+        }
+    }
 
+    /// Simulate the network running each layer in its own thread (see Engine::Threaded)
+    fn run_threaded(&mut self, input: &[Vec<bool>], probes: &Probes) -> TracedRun {
         // Number of time steps to simulate in the SNN, equal to the number of columns
         // of the 'input' matrix
         let snn_time_steps_number = input[0].len();
 
-        //Time step duration converted to milliseconds to perform computation later
-        let time_step_duration_ms = self.time_step_duration_us / 1000.0;
-
-        // number of layers in the network
-        let number_of_layers = self.layers.len();
-
         // create the boolean matrix used to hold the result of the simulation. The matrix has as many
         // rows as the number of output neurons and as many columns ad the number of time steps involved
         // in the simulation. If output[i][j] == true, it means that the exit 'i' produced a Pulse at time
@@ -236,164 +694,321 @@ impl Network {
         let mut output = self.create_output_matrix(snn_time_steps_number);
 
         // Each layer of the SNN is run in a separated thread. Different layers are able to
-        // communicate, i.e. exchange Pulses or Control Messages, using channels; each layer
+        // communicate, i.e. exchange Pulses or Control Messages, using channels: there is a
+        // channel for each source of each layer (see Topology::channels), so that each layer
         // has access to:
-        // - the receiver side of a channel to read messages from previous layer
-        // - the sender side of another channel to send messages to the following layer
+        // - the receiver side of a channel for each of its sources, i.e. the layers - or the
+        //      entrances - it receives pulses from
+        // - the sender side of a channel for each layer receiving its pulses, plus the one
+        //      of the output channel if it is the last layer
         //
-        // Example:
+        // Example, for a chain:
         // {input} [tx_0]--->[rx_0] {layer 0} [tx_1]--->[rx_1] {layer 1} [tx_2]--->[rx_2] ...
         //
-        // Usage of each channel side:
-        // - input_injection_sender: it is used only once, to transfer the Pulses contained
-        //      in the input matrix to layer 0 of the SNN, ordered by time step (all
-        //      pulses sent for a certain time step are followed by a Control Message - GoAhead-
-        //      used to signal that there are no more Pulses for that time step, so that the
-        //      listener can stop waiting for messages on the channel and start its computation
-        //      before starting waiting for Pulses belonging to the next time step).
-        // - receiver_from_previous_layer: it represents the channel side used by each layer
-        //      to receive Messages from the previous layer. Once the thread for a specific
-        //      layer is spawned, this variable is updated with the content of
-        //      'future_receiver_from_previous_layer' and fed again to the next thread.
-        // - sender_to_following_layer: it is used by a layer to send Messages to the following
-        //      layer. Once the thread is spawned, this variable is updated with the sender side of
-        //      a newly created channel
-        // - future_receiver_from_previous_layer: it represents the receiver coupled with the
-        //      sender used to transfer messages to the following layer, so, once the thread is
-        //      spawned, this receiver must be assigned to the variable 'receiver_from_previous_layer',
-        //      so that it becomes the input channel for the next layer. Then its content is updated with
-        //      the receiver of a newly created channel
-
-        let (input_injection_sender, mut receiver_from_previous_layer) = mpsc::channel();
-        let (mut sender_to_following_layer, mut future_receiver_from_previous_layer) =
-            mpsc::channel();
-
-        // Injecting Pulses from input matrix to layer 0, ordered by time step and separated
-        // by using a GoAhead control message
-        for time_step in 0..snn_time_steps_number {
-            for input_node in 0..snn_inputs_number {
-                if input[input_node][time_step] {
-                    input_injection_sender
-                        .send(Message::Pulse(input_node))
-                        .unwrap();
-                }
-            }
-            input_injection_sender.send(Message::GoAhead).unwrap();
-        }
+        // The input injection senders are used only once, to transfer the Pulses contained in
+        // the input matrix to the layers receiving them, ordered by time step (all pulses sent
+        // for a certain time step are followed by a Control Message - GoAhead- used to signal
+        // that there are no more Pulses for that time step, so that the listener can stop
+        // waiting for messages on the channel and start its computation before starting
+        // waiting for Pulses belonging to the next time step). A layer waits for the GoAhead
+        // of each of its sources before starting its computation for a time step.
+        let channels = Topology::of(self).channels();
+
+        // Injecting Pulses from input matrix to the layers receiving them, ordered by time
+        // step and separated by using a GoAhead control message
+        let first_time_step = self.time_step;
+        Self::inject_input(&channels.input_senders, input, first_time_step);
 
         // Create a Vec to hold thread handles
-        let mut thread_handles = Vec::<JoinHandle<()>>::new();
+        let mut thread_handles = Vec::<JoinHandle<LayerRunResult>>::new();
+
+        // Trace holding the values of the probed Registers: each layer records the values of
+        // the Registers belonging to it
+        let mut trace = Trace::new(probes);
 
         // Spawning a thread for each layer
-        for layer_nr in 0..number_of_layers {
+        for (layer_nr, layer_channels) in channels.layers.into_iter().enumerate() {
             // Each thread takes possession of the Vec containing the Neurons
-            // for the corresponding layer
-            let mut layer_neurons = self.layers.remove(0);
+            // for the corresponding layer, and of its channels
+            let layer = self.layers.remove(0);
+            let layer_run = LayerRun::new(
+                self,
+                layer,
+                layer_nr,
+                layer_channels,
+                probes,
+                snn_time_steps_number,
+            );
 
             let join_handle = thread::Builder::new()
                 .name(format!("layer {}", layer_nr))
-                .spawn(move || {
-                    // Vec to keep track of all neurons of the current layer that had emitted pulses
-                    // during the previous time step. This is needed to apply inhibitive contribution
-                    // to the membrane potential of each neuron, so that the right internal weights
-                    // can be selected for computation.
-                    let mut emitted_pulse_sources = Vec::new();
-
-                    // each layer operates one time step at a time, in order. In order to perform
-                    // computation for time step 'k', it is necessary that the layer has received all
-                    // Pulses emitted during the SAME time step by the previous layer.
-                    // In other words, layer 'n' can only process time step 'k' data when layer 'n-1'
-                    // has terminated its own computation on the same time step 'k'
-                    for time_step in 0..snn_time_steps_number {
-                        // Vec to keep track of the origin of each Pulse received during the current time step,
-                        // i.e. the index of the neuron belonging to the previous layer - or entrance - which generated
-                        // the Pulse itself; this is needed to allow the Neurons to choose the right Weight when
-                        // computing the new Membrane Potential
-                        let mut pulse_sources = Vec::new();
-
-                        // Receive all pulses for the current time step
-                        while let Ok(Message::Pulse(source)) = receiver_from_previous_layer.recv() {
-                            pulse_sources.push(source);
-                        }
+                .spawn(move || layer_run.execute());
 
-                        // apply inhibitive contribution due to pulses generated by nodes of the current layer
-                        // during previous time step (except for time step 0)
-                        if time_step > 0 {
-                            for neuron in layer_neurons.iter_mut() {
-                                neuron.inhibite_after_pulses_emission(
-                                    &emitted_pulse_sources,
-                                    time_step,
-                                    time_step_duration_ms,
-                                    self.model,
-                                );
-                            }
-                            // all neurons updated: clear emitted_pulse_sources Vec so that it can
-                            // be used again, for next iteration
-                            emitted_pulse_sources.clear();
-                        }
+            // Push the join handle inside the Vec
+            thread_handles.push(join_handle.unwrap());
+        }
 
-                        // Update the status for the layer Neurons ONLY if at least a pulse
-                        // is received by the layer, otherwise there is no need to do that.
-                        if pulse_sources.len() > 0 {
-                            // Feed Pulses to all neurons in the layer
-                            for (i, neuron) in layer_neurons.iter_mut().enumerate() {
-                                // if the current neuron 'fires', send a Pulse over the channel
-                                // to the following layer
-                                if neuron.feed_pulses(
-                                    &pulse_sources,
-                                    time_step,
-                                    time_step_duration_ms,
-                                    self.model,
-                                ) {
-                                    // add current neuron to emitted_pulse_sources
-                                    emitted_pulse_sources.push(i);
-                                    // send pulses over the channel
-                                    sender_to_following_layer.send(Message::Pulse(i)).unwrap();
-                                }
-                            }
+        // Await termination of all spawned threads, putting layers back inside the network and
+        // collecting the values recorded by the probes of each layer inside the trace.
+        // If a layer panicked (e.g. because of a time step mismatch), the panic is propagated
+        thread_handles
+            .into_iter()
+            .enumerate()
+            .for_each(|(layer_nr, join_handle)| {
+                let (layer, probed_values) = join_handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                self.layers.push(layer);
+                trace.collect(probes, layer_nr, probed_values);
+            });
+        trace.fill_missing_layers(self.layers.len(), snn_time_steps_number);
 
-                            // Signal to the following layer that all pulses for this time step
-                            // have been sent, by sending a GoAhead Control Message
-                            sender_to_following_layer.send(Message::GoAhead).unwrap();
-                        }
-                    }
-                });
+        // write results to the output boolean matrix
+        Self::write_results(&channels.output_receiver, first_time_step, &mut output);
+        self.time_step += snn_time_steps_number;
 
-            // Push the join handle inside the Vec
-            thread_handles.push(join_handle.unwrap());
+        TracedRun { output, trace }
+    }
 
-            // prepare the channels for the following thread (layer) to spawn
+    /// Simulate the network running all layers in the calling thread (see Engine::Sequential).
+    /// Time steps are simulated one at a time through a Session, which runs the same
+    /// per-layer code as the threaded engine.
+    fn run_sequentially(&mut self, input: &[Vec<bool>], probes: &Probes) -> TracedRun {
+        // Number of time steps to simulate in the SNN, equal to the number of columns
+        // of the 'input' matrix
+        let snn_time_steps_number = input[0].len();
 
-            // receiver_from_previous_layer is assigned the receiver coupled with the transmitter
-            // of the previous thread, so that it can be used by the next thread that will be spwaned
-            // to read Messages produced by the previous thread
-            receiver_from_previous_layer = future_receiver_from_previous_layer;
+        // boolean matrix holding the result of the simulation (see run_threaded)
+        let mut output = self.create_output_matrix(snn_time_steps_number);
+        let mut trace = Trace::new(probes);
+
+        let mut session = self.open_session();
+        for column in 0..snn_time_steps_number {
+            let time_step = session.time_step();
+            let output_pulses = session.step(&Self::input_pulses_at(input, column));
+            Self::write_time_step_results(&mut output, column, output_pulses);
+
+            trace.record(session.network_mut(), time_step);
+        }
 
-            // The next thread that will be spawned has been given the receiver side, but it still does
-            // not have a transmitter.
+        TracedRun { output, trace }
+    }
+
+    /// Simulate the network on PackedLayers, one layer after the other in the calling thread
+    /// (see Engine::Packed). The state reached by the PackedLayers is stored back into the
+    /// layers of the network at the end of the run.
+    fn run_packed(&mut self, input: &[Vec<bool>], probes: &Probes) -> TracedRun {
+        let snn_time_steps_number = input[0].len();
+        let time_step_duration_ms = self.time_step_duration_us / 1000.0;
 
-            // If the last thread has already been spawned, there is no next thread, so there is no need
-            // to create another transmitter
-            if layer_nr == number_of_layers - 1 {
-                break;
+        let mut output = self.create_output_matrix(snn_time_steps_number);
+        let mut trace = Trace::new(probes);
+
+        // the layers packed by the previous run are reused, if the network still has the
+        // same layers
+        let mut packed_layers = std::mem::take(&mut self.packed_layers);
+        if packed_layers.len() == self.layers.len() {
+            for (packed_layer, layer) in packed_layers.iter_mut().zip(&self.layers) {
+                packed_layer.load(layer);
+            }
+        } else {
+            packed_layers = self.layers.iter().map(PackedLayer::from).collect();
+        }
+        let neuron_models = self
+            .layers
+            .iter()
+            .map(|layer| layer.resolve_models(&self.model))
+            .collect::<Vec<_>>();
+        let mut noise_sources = (0..self.layers.len())
+            .map(|layer_nr| self.noise.map(|noise| NoiseSource::new(noise, layer_nr)))
+            .collect::<Vec<Option<NoiseSource>>>();
+        let layer_faults = (0..self.layers.len())
+            .map(|layer_nr| self.faults.of_layer(layer_nr).to_vec())
+            .collect::<Vec<Vec<Fault>>>();
+
+        let topology = Topology::of(self);
+
+        for column in 0..snn_time_steps_number {
+            let time_step = self.time_step + column;
+            // entrances of the network which received a pulse
+            let input_pulse_sources = (0..self.nr_inputs)
+                .filter(|input_node| input[*input_node][column])
+                .collect::<Vec<usize>>();
+            // pulses emitted by the layers during the previous time step, fed back to the
+            // layers receiving them
+            let fed_back_pulses = topology.fed_back_pulses(time_step, |layer_nr| {
+                packed_layers[layer_nr].emitted_pulse_sources()
+            });
+            let mut emitted_pulses = Vec::<Vec<usize>>::with_capacity(packed_layers.len());
+            for (layer_nr, layer) in packed_layers.iter_mut().enumerate() {
+                let pulse_sources = topology.gather(
+                    layer_nr,
+                    &input_pulse_sources,
+                    |source_layer_nr| &emitted_pulses[source_layer_nr],
+                    |source_layer_nr| &fed_back_pulses[source_layer_nr],
+                );
+                let emitted_pulse_sources = layer.step(
+                    &pulse_sources,
+                    time_step,
+                    time_step_duration_ms,
+                    &neuron_models[layer_nr],
+                    noise_sources[layer_nr].as_mut(),
+                    &layer_faults[layer_nr],
+                );
+                emitted_pulses.push(emitted_pulse_sources);
+            }
+            for source_index in emitted_pulses.last().into_iter().flatten() {
+                output[*source_index][column] = true;
             }
 
-            // Otherwise, a new channel is created, whose transmitter is given to the next thread
-            (
-                sender_to_following_layer,
-                future_receiver_from_previous_layer,
-            ) = mpsc::channel();
+            trace.record_packed(&packed_layers, &self.faults, time_step);
         }
 
-        // Await termination of all spawned threads
-        thread_handles.into_iter().for_each(|join_handle| {
-            join_handle.join().unwrap();
-        });
+        for (layer, packed_layer) in self.layers.iter_mut().zip(&packed_layers) {
+            packed_layer.store_state(layer);
+        }
+        self.packed_layers = packed_layers;
+        self.time_step += snn_time_steps_number;
 
-        // write results to the output boolean matrix
-        Self::write_results(receiver_from_previous_layer, &mut output);
+        TracedRun { output, trace }
+    }
+
+    /// Simulate the network like 'run_in_place', saving a Checkpoint of the state of the
+    /// network at the beginning of each time step. The run is performed through a Session,
+    /// i.e. with the sequential engine, whatever the Engine of the network.
+    pub fn run_with_checkpoints(&mut self, input: &[Vec<bool>]) -> GoldenRun {
+        let snn_time_steps_number = input[0].len();
+        let mut output = self.create_output_matrix(snn_time_steps_number);
+        let mut checkpoints = Vec::with_capacity(snn_time_steps_number);
+
+        let mut session = self.open_session();
+        for time_step in 0..snn_time_steps_number {
+            checkpoints.push(session.checkpoint());
+            let output_pulses = session.step(&Self::input_pulses_at(input, time_step));
+            Self::write_time_step_results(&mut output, time_step, output_pulses);
+        }
+
+        GoldenRun {
+            output,
+            checkpoints,
+        }
+    }
+
+    /// Simulate the network in presence of a fault, starting from the Checkpoint of the
+    /// run without damages ('golden_run') taken at the first time step the fault can affect:
+    /// the time step of the bit flip for a TransientBitFlip, the first one of the run
+    /// otherwise. The output for the previous time steps is the one of the run without
+    /// damages.
+    ///
+    /// If early termination is enabled, once the fault is no longer active the state of
+    /// the network is compared with the Checkpoints of the run without damages after each
+    /// time step: as soon as they match, the fault has been masked, so the run is stopped
+    /// and the output for the following time steps is the one of the run without damages.
+    ///
+    /// At the end of the run the network keeps its state, without the fault.
+    pub fn resume_with_fault(
+        &mut self,
+        golden_run: &GoldenRun,
+        input: &[Vec<bool>],
+        fault: Fault,
+    ) -> FaultyRun {
+        let early_termination = self.early_termination;
+
+        let mut faulty_run = FaultyRun {
+            output: golden_run.output.clone(),
+            masked_at: None,
+        };
+
+        // column 'j' of the output corresponds to the time step of the network at which
+        // the run without damages started, plus 'j'
+        let run_start = golden_run
+            .checkpoints
+            .first()
+            .map_or(0, Checkpoint::time_step);
+
+        // column from which the fault can affect the network, and column after which it is
+        // no longer active (if any)
+        let (first_column, last_active_column) = match fault.damage {
+            Damage::TransientBitFlip { time_step, .. } => match time_step.checked_sub(run_start) {
+                Some(column) => (column, Some(column)),
+                // the bit flip happens before the beginning of the run: the fault has no effect
+                None => return faulty_run,
+            },
+            _ => (0, None),
+        };
 
-        return output;
+        // the bit flip happens after the end of the input: the fault has no effect
+        if first_column >= golden_run.checkpoints.len() {
+            return faulty_run;
+        }
+
+        let mut session = self.open_session();
+        session.restore(&golden_run.checkpoints[first_column]);
+        session.apply_damage(fault);
+        for column in first_column..input[0].len() {
+            let output_pulses = session.step(&Self::input_pulses_at(input, column));
+            Self::write_time_step_results(&mut faulty_run.output, column, output_pulses);
+
+            // the fault is masked if it is no longer active and the state after this time step
+            // is the same as the one saved before the next time step of the run without damages
+            let fault_is_active = last_active_column.is_none_or(|last| column < last);
+            if early_termination && !fault_is_active {
+                if let Some(checkpoint) = golden_run.checkpoints.get(column + 1) {
+                    if session.matches(checkpoint) {
+                        faulty_run.masked_at = Some(column);
+                        break;
+                    }
+                }
+            }
+        }
+        session.remove_damage(fault.address);
+
+        faulty_run
+    }
+
+    /// Get the pulses received by the entrances of the SNN at the specified time step,
+    /// i.e. the corresponding column of the 'input' matrix
+    fn input_pulses_at(input: &[Vec<bool>], time_step: usize) -> Vec<bool> {
+        input.iter().map(|entrance| entrance[time_step]).collect()
+    }
+
+    /// Write the pulses produced by the output neurons at a time step inside the corresponding
+    /// column of the 'output' boolean matrix
+    fn write_time_step_results(output: &mut [Vec<bool>], time_step: usize, pulses: Vec<bool>) {
+        for (output_index, pulse) in pulses.into_iter().enumerate() {
+            output[output_index][time_step] = pulse;
+        }
+    }
+
+    /// Simulate the network together with a faulty copy of it for each of the provided
+    /// faults, in a single pass (see concurrent::run)
+    pub fn run_concurrently(&mut self, input: &[Vec<bool>], faults: &[Fault]) -> ConcurrentRun {
+        concurrent::run(self, input, faults)
+    }
+
+    /// Trace the values of the probed Registers during a run without damages and during a run
+    /// in which the provided fault is applied, so that the two can be compared to follow how
+    /// the damage propagates through the network. Both runs start from the reset state and
+    /// the network itself is left untouched. The run without damages is returned first.
+    pub fn trace_fault(
+        &self,
+        fault: Fault,
+        input: &[Vec<bool>],
+        probes: &Probes,
+    ) -> (TracedRun, TracedRun) {
+        let mut snn = self.clone();
+
+        snn.reset_state();
+        let golden = snn.run_with_probes(input, probes);
+
+        snn.reset_state();
+        snn.restore_homeostasis_from(self);
+        if !snn.apply_damage(fault) {
+            panic!("Cannot apply the fault: the damaged register does not exist");
+        }
+        let faulty = snn.run_with_probes(input, probes);
+
+        (golden, faulty)
     }
 
     /// Simulate the behaviour of the SNN in presence of damages to its fundamental elements.
@@ -402,6 +1017,9 @@ impl Network {
     /// type of damage to only ONE random element whose type is chosen among those specified in
     /// the 'faulty_elements' parameter.
     /// 'input' boolean matrix is used to feed the desired input to the SNN.
+    ///
+    /// Panics if checkpointing or early termination are enabled with an Engine other than
+    /// the sequential or the concurrent one.
     pub fn simulate(
         &self,
         faulty_elements: Vec<FaultyElement>,
@@ -414,6 +1032,13 @@ impl Network {
             return None;
         }
 
+        // checkpoints are taken by simulating the network through a Session
+        assert!(
+            !(self.checkpointing || self.early_termination)
+                || matches!(self.engine, Engine::Sequential | Engine::Concurrent),
+            "checkpointing and early termination require the sequential or concurrent engine"
+        );
+
         // create Simulation Result matrix
         let mut simulation_result_matrix = Vec::new();
         for i in 0..self.nr_outputs {
@@ -423,21 +1048,117 @@ impl Network {
             }
         }
 
-        // run the simulation without applying any damages to network elements
-        let output_without_damages = self.clone().run(input.clone());
+        // the network is cloned only once: the same instance is used for all runs, resetting
+        // its state before each of them and removing the damage applied after each of them
+        let mut snn = self.clone();
+
+        // with the concurrent engine, the damages are chosen in advance and all the faulty runs
+        // are simulated in a single pass, together with the run without damages
+        let concurrent_run = (self.engine == Engine::Concurrent).then(|| {
+            let damages = (0..iterations)
+                .map(|_| {
+                    snn.choose_damage(damage_type, &faulty_elements, input[0].len())
+                        .unwrap()
+                })
+                .collect::<Vec<_>>();
+            let faults = damages
+                .iter()
+                .filter_map(|(_, fault)| *fault)
+                .collect::<Vec<Fault>>();
+            snn.reset_state();
+            let run = concurrent::run(&mut snn, &input, &faults);
+            (damages, run)
+        });
+
+        // run the simulation without applying any damages to network elements. With
+        // checkpointing or early termination, the state of the network at each time step
+        // is saved as well
+        snn.reset_state();
+        let golden_run = (concurrent_run.is_none()
+            && (self.checkpointing || self.early_termination))
+            .then(|| snn.run_with_checkpoints(&input));
+
+        // number of faults masked before the end of the run, if early termination is enabled
+        let mut masked_faults = self.early_termination.then_some(0);
+        let mut count_if_masked = |faulty_run: &FaultyRun| {
+            if let (Some(masked_faults), Some(_)) = (&mut masked_faults, faulty_run.masked_at) {
+                *masked_faults += 1;
+            }
+        };
+
+        let output_without_damages = match (&concurrent_run, &golden_run) {
+            (Some((_, concurrent_run)), _) => concurrent_run.output.clone(),
+            (None, Some(golden_run)) => golden_run.output.clone(),
+            (None, None) => snn.run_in_place(&input),
+        };
+
+        // damages chosen in advance and the corresponding faulty runs, if any
+        let mut concurrent_runs = concurrent_run.map(|(damages, run)| {
+            let mut faulty_runs = run.faulty_runs.into_iter();
+            damages
+                .into_iter()
+                .map(move |(damage_detail, fault)| {
+                    (damage_detail, fault.map(|_| faulty_runs.next().unwrap()))
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+        });
 
         // run the simulation as many times as specified by 'iterations' parameter, applying the
         // the chosen DamageModel ('damage_type') each time to a different element chosen randomly among
         // those specified in the 'faulty_elements' Vec.
         for iteration_number in 0..iterations {
-            // clone the network, so that each instance can be Damaged independently
-            let mut snn = self.clone();
-            // apply damage to the snn
-            let damage_detail =
-                Self::apply_damage_to_snn(&mut snn, damage_type, &faulty_elements, input[0].len())
-                    .unwrap();
+            let (damage_detail, output_with_damage) = match concurrent_runs.as_mut() {
+                // the faulty run has already been simulated
+                Some(concurrent_runs) => {
+                    let (damage_detail, faulty_run) = concurrent_runs.next().unwrap();
+                    let output_with_damage = match faulty_run {
+                        Some(faulty_run) => {
+                            count_if_masked(&faulty_run);
+                            faulty_run.output
+                        }
+                        // no Register has been damaged: the output is the same as without damages
+                        None => output_without_damages.clone(),
+                    };
+                    (damage_detail, output_with_damage)
+                }
+                None => {
+                    // choose the damage to apply to the snn
+                    let (damage_detail, fault) = snn
+                        .choose_damage(damage_type, &faulty_elements, input[0].len())
+                        .unwrap();
 
-            let output_with_damage = Self::run(snn, input.clone());
+                    let output_with_damage = match (&golden_run, fault) {
+                        // start from the state saved when the fault first affects the network
+                        (Some(golden_run), Some(fault)) => {
+                            let faulty_run = snn.resume_with_fault(golden_run, &input, fault);
+                            count_if_masked(&faulty_run);
+                            faulty_run.output
+                        }
+                        // no Register has been damaged: the output is the same as without damages
+                        (Some(golden_run), None) => golden_run.output.clone(),
+                        (None, fault) => {
+                            // start from the same state as the run without damages
+                            snn.reset_state();
+                            snn.restore_homeostasis_from(self);
+
+                            if let Some(fault) = fault {
+                                snn.apply_damage(fault);
+                            }
+
+                            let output_with_damage = snn.run_in_place(&input);
+
+                            // remove the damage, so that the network can be used for the next iteration
+                            if let Some(fault) = fault {
+                                snn.remove_damage(fault.address);
+                            }
+
+                            output_with_damage
+                        }
+                    };
+                    (damage_detail, output_with_damage)
+                }
+            };
 
             // compare matrix to the one obtained without damages, updating result matrix
             Self::compare_outputs(
@@ -467,6 +1188,7 @@ impl Network {
             type_of_damage: damage_type,
             output_without_damages,
             diffs: simulation_result_matrix,
+            masked_faults,
         });
     }
 
@@ -491,14 +1213,16 @@ impl Network {
         }
     }
 
-    /// Apply a single-bit Damage to the network: one element is chosen randomly among those
-    /// listed in 'faulty_elements', and the specified DamageModel is applied.
-    fn apply_damage_to_snn(
-        &mut self,
+    /// Choose a single-bit Damage to apply to the network: one element is chosen randomly among
+    /// those listed in 'faulty_elements', and the specified DamageModel is used. The network is
+    /// not modified: the returned Fault (None if the chosen element does not exist, e.g. a
+    /// Neuron without delay lines) must be applied with apply_damage.
+    fn choose_damage(
+        &self,
         damage_type: DamageModel,
         faulty_elements: &Vec<FaultyElement>,
         number_of_time_steps: usize,
-    ) -> Option<DamageDetail> {
+    ) -> Option<(DamageDetail, Option<Fault>)> {
         // create a random number generator
         let mut rng = thread_rng();
 
@@ -508,11 +1232,11 @@ impl Network {
             Some(faulty_element) => {
                 // choose a random layer
                 let index_of_layer_to_damage = rand::thread_rng().gen_range(0..self.layers.len());
-                let layer_to_damage = &mut self.layers[index_of_layer_to_damage];
+                let layer_to_damage = &self.layers[index_of_layer_to_damage];
                 // choose a random neuron
                 let index_of_neuron_to_damage =
                     rand::thread_rng().gen_range(0..layer_to_damage.len());
-                let neuron_to_damage = &mut layer_to_damage[index_of_neuron_to_damage];
+                let neuron_to_damage = &layer_to_damage.neurons[index_of_neuron_to_damage];
                 // choose bit position where to apply the damage (between 0 and 63 - since
                 // Registers are on 64 bits)
                 let bit_position = rng.gen_range(0..64) as usize;
@@ -529,60 +1253,71 @@ impl Network {
                     },
                 };
 
-                // apply damage to the correct Register
-                match faulty_element {
+                // choose the Register to damage
+                let register = match faulty_element {
                     FaultyElement::Weights => {
-                        // choose randomly whether to damage external or internal weights
-                        let weights_vec = if rng.gen_bool(0.5) {
-                            &mut neuron_to_damage.weights
-                        } else {
-                            &mut neuron_to_damage.internal_weights
+                        // choose randomly a weight to damage among those of the synapses
+                        // which exist: sparsely connected Neurons only have weights for their
+                        // synapses, and internal weights are only read by layers using dense
+                        // lateral inhibition (if there is no synapse, nothing is damaged)
+                        let weights_number = neuron_to_damage.weights.len();
+                        let internal_weights_number = match layer_to_damage.inhibition {
+                            LateralInhibition::Dense => neuron_to_damage.internal_weights.len(),
+                            LateralInhibition::Uniform { .. }
+                            | LateralInhibition::KWinnerTakeAll { .. } => 0,
                         };
-                        // choose randomly a weight to damage
-                        let weight = weights_vec.choose_mut(&mut rng).unwrap();
-                        // apply damage to the Register containing the weight
-                        weight.apply_damage(damage);
-                    }
-                    FaultyElement::Thresholds => {
-                        // apply damage to the Register containing v_th
-                        neuron_to_damage.v_th.apply_damage(damage);
-                    }
-                    FaultyElement::MembranePotentials => {
-                        // apply damage to the Register containing v_mem
-                        neuron_to_damage.v_mem.apply_damage(damage);
-                    }
-                    FaultyElement::ResetPotentials => {
-                        // apply damage to the Register containing v_reset
-                        neuron_to_damage.v_reset.apply_damage(damage);
-                    }
-                    FaultyElement::PotentialsAtRest => {
-                        // apply damage to the Register containing v_rest
-                        neuron_to_damage.v_rest.apply_damage(damage);
-                    }
-                    FaultyElement::Comparator => {
-                        neuron_to_damage.cmp_reg.apply_damage(damage);
-                    }
-                    FaultyElement::Adder => {
-                        neuron_to_damage.add_reg.apply_damage(damage);
-                    }
-                    FaultyElement::Multiplier => {
-                        neuron_to_damage.mul_reg.apply_damage(damage);
+                        let synapses_number = weights_number + internal_weights_number;
+                        (synapses_number > 0).then(|| {
+                            let index = rng.gen_range(0..synapses_number);
+                            if index < weights_number {
+                                NeuronRegister::Weight(index)
+                            } else {
+                                NeuronRegister::InternalWeight(index - weights_number)
+                            }
+                        })
                     }
-                    FaultyElement::Divider => {
-                        neuron_to_damage.div_reg.apply_damage(damage);
+                    FaultyElement::Thresholds => Some(NeuronRegister::Threshold),
+                    FaultyElement::MembranePotentials => Some(NeuronRegister::MembranePotential),
+                    FaultyElement::ResetPotentials => Some(NeuronRegister::ResetPotential),
+                    FaultyElement::PotentialsAtRest => Some(NeuronRegister::PotentialAtRest),
+                    FaultyElement::Comparator => Some(NeuronRegister::Comparator),
+                    FaultyElement::Adder => Some(NeuronRegister::Adder),
+                    FaultyElement::Multiplier => Some(NeuronRegister::Multiplier),
+                    FaultyElement::Divider => Some(NeuronRegister::Divider),
+                    FaultyElement::Delays => {
+                        // choose randomly a delay line to damage (Neurons without delay
+                        // lines deliver all pulses immediately, so there is nothing to damage)
+                        if neuron_to_damage.delays.is_empty() {
+                            None
+                        } else {
+                            Some(NeuronRegister::Delay(
+                                rng.gen_range(0..neuron_to_damage.delays.len()),
+                            ))
+                        }
                     }
-                }
+                    FaultyElement::Biases => Some(NeuronRegister::Bias),
+                };
+                let fault = register.map(|register| Fault {
+                    address: RegisterAddress {
+                        layer: index_of_layer_to_damage,
+                        neuron: index_of_neuron_to_damage,
+                        register,
+                    },
+                    damage,
+                });
 
                 // struct which describes the damage in detail. The field 'at_iteration' here is dummy,
                 // since it will be replaced if and when a difference between the expected output and
                 // actual one is found.
-                return Some(DamageDetail {
+                let damage_detail = DamageDetail {
                     at_iteration: 0,
                     damage_type: *faulty_element,
                     at_layer: index_of_layer_to_damage,
                     at_neuron: index_of_neuron_to_damage,
+                    at_register: register,
                     at_bit: bit_position,
-                });
+                };
+                return Some((damage_detail, fault));
             }
             // if no element is found, return
             None => {
@@ -590,4 +1325,72 @@ impl Network {
             }
         }
     }
+
+    /// Get a reference to the Register at the specified address, if it exists
+    pub fn register(&self, address: RegisterAddress) -> Option<&Register> {
+        self.layers
+            .get(address.layer)?
+            .neurons
+            .get(address.neuron)?
+            .register(address.register)
+    }
+
+    /// Get a mutable reference to the Register at the specified address, if it exists
+    pub fn register_mut(&mut self, address: RegisterAddress) -> Option<&mut Register> {
+        self.layers
+            .get_mut(address.layer)?
+            .neurons
+            .get_mut(address.neuron)?
+            .register_mut(address.register)
+    }
+
+    /// Apply a Fault to the network, i.e. add it to the FaultOverlay of the network, so that
+    /// its Damage affects the Register at its address when the network is simulated. The
+    /// Register itself is not modified.
+    /// Returns false if the address does not correspond to any Register.
+    pub fn apply_damage(&mut self, fault: Fault) -> bool {
+        if self.register(fault.address).is_none() {
+            return false;
+        }
+        self.faults.insert(fault);
+        true
+    }
+
+    /// Remove the Damage applied with apply_damage to the Register at the specified address
+    pub fn remove_damage(&mut self, address: RegisterAddress) {
+        self.faults.remove(address);
+    }
+
+    /// Restore the state of all Neurons to the one they have before any simulation: see
+    /// Layer::reset_state. The following run starts again from time step 0. Parameters,
+    /// damages and thresholds adapted by homeostasis are left unchanged.
+    pub fn reset_state(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.reset_state();
+        }
+        self.time_step = 0;
+    }
+
+    /// Get the time step the next run of the network starts from, i.e. the number of time
+    /// steps simulated since the network has been created or its state has been reset.
+    /// Each run continues from the time steps of the previous ones, so that the state of
+    /// the Neurons (e.g. leakage and pulses waiting on delayed synapses) evolves as in a
+    /// single run, and the time step of a TransientBitFlip refers to these time steps.
+    pub fn time_step(&self) -> usize {
+        self.time_step
+    }
+
+    /// Copy thresholds and homeostasis state of all Neurons from 'network', which must have
+    /// the same structure. It is used to undo the adaptation performed by homeostasis.
+    fn restore_homeostasis_from(&mut self, network: &Network) {
+        for (layer, source_layer) in self.layers.iter_mut().zip(&network.layers) {
+            if source_layer.homeostasis.is_none() {
+                continue;
+            }
+            for (neuron, source_neuron) in layer.neurons.iter_mut().zip(&source_layer.neurons) {
+                neuron.v_th.write_value(source_neuron.v_th.stored_value());
+                neuron.homeostasis_state = source_neuron.homeostasis_state;
+            }
+        }
+    }
 }
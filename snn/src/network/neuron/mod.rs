@@ -1,6 +1,10 @@
+use crate::network::convolution::ReceptiveField;
+use crate::network::dynamics::NeuronDynamics;
+use crate::network::noise::NoiseSource;
+use crate::network::{Homeostasis, ResetMode};
 use crate::register::Register;
-
-use super::NeuronModel;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// The Neuron struct represents a neuron of the spiking neural network.
 /// A neuron is characterized by a series of parameters which describe its
@@ -14,9 +18,38 @@ use super::NeuronModel;
 ///
 /// - a Vec in which the 'i'th cell contains the value of the weight assigned
 /// to the synapse going from the 'i'th neuron in the previous layer to that neuron.
+///   If the neuron is sparsely connected, it only has synapses from some neurons of the
+///   previous layer, and the 'i'th cell refers instead to the synapse going from the
+///   neuron whose index is in the 'i'th cell of synapse_sources.
 /// - a Vec in which the 'i'th cell contains the value of the weight assigned
 /// to the internal synapse going from the 'i'th neuron in the SAME layer to that neuron.
 /// (synapse going from a node to itself can be assigned weight 0.0)
+/// - a Vec in which the 'i'th cell contains the delay (in time steps) of the synapse
+///   going from the 'i'th neuron in the previous layer to that neuron (or of the 'i'th
+///   synapse, if the neuron is sparsely connected). If it is empty, all pulses are
+///   delivered during the same time step they are produced.
+/// - the buffer of pulses which have been received on delayed synapses and are
+///   waiting for their delivery time step.
+///
+/// If the layer of the neuron has its own sources (see Layer::sources), the neurons of
+/// the previous layer are those of all its sources, one source after the other.
+///
+/// If the layer of the neuron regulates thresholds by homeostasis, the neuron keeps
+/// track of the pulses it emitted during the current window.
+///
+/// A neuron can optionally specify its own model (any type implementing
+/// NeuronDynamics): if it is None, the model of the layer it belongs to is used.
+///
+/// A sparsely connected neuron only receives the pulses produced by the neurons it has
+/// a synapse from: no Register exists for the missing synapses.
+///
+/// A neuron of a convolutional layer has a receptive field instead: its 'weights' are the
+/// kernel shared by all the neurons of the layer, and it only receives the pulses produced
+/// inside its window, each one weighted by the corresponding kernel weight (see Kernel).
+///
+/// The Vecs of synapse Registers (weights, internal weights and delays) are shared
+/// by the clones of a neuron, since they do not change during a simulation: they
+/// are copied only when one of their Registers is modified (see register_mut).
 #[derive(Clone)]
 pub struct Neuron {
     pub v_th: Register, // (mV) threshold voltage: if v_mem goes upper than this value, then a spike is produced as output
@@ -25,12 +58,19 @@ pub struct Neuron {
     pub tau: Register, // (ms) time constant for exponential v_mem decrease in absence of received pulses
     pub v_mem: Register, // (mV) membrane potential after receiving last pulse
     pub last_received_pulse_step: usize, // discrete time step when last pulse was received
-    pub weights: Vec<Register>, // weights of each synapse going from the 'i'th neuron of the previous layer to this neuron
-    pub internal_weights: Vec<Register>, //weights of synapses internal to layer
-    pub add_reg: Register,      // register which contains the output of adder
-    pub mul_reg: Register,      // register which contains the output of multiplier
-    pub cmp_reg: Register,      // register which contains the output of comparator
-    pub div_reg: Register,      // register which contains the output of divider
+    pub weights: Arc<Vec<Register>>, // weights of each synapse going from the 'i'th neuron of the previous layer to this neuron
+    pub synapse_sources: Option<Arc<Vec<usize>>>, // if sparsely connected, the source of each synapse in 'weights', in increasing order
+    pub receptive_field: Option<ReceptiveField>, // if in a convolutional layer, the position of the neuron
+    pub internal_weights: Arc<Vec<Register>>,    //weights of synapses internal to layer
+    pub bias: Register, // constant current added to v_mem on every time step
+    pub delays: Arc<Vec<Register>>, // delays (in time steps) of each synapse going from the 'i'th neuron of the previous layer to this neuron
+    pub delayed_pulses: Vec<DelayedPulse>, // pulses received on delayed synapses, not yet delivered
+    pub add_reg: Register,          // register which contains the output of adder
+    pub mul_reg: Register,          // register which contains the output of multiplier
+    pub cmp_reg: Register,          // register which contains the output of comparator
+    pub div_reg: Register,          // register which contains the output of divider
+    pub model: Option<Arc<dyn NeuronDynamics>>, // model overriding the one of the layer, if any
+    pub homeostasis_state: HomeostasisState, // firing activity measured for homeostasis
 }
 
 impl Default for Neuron {
@@ -43,12 +83,19 @@ impl Default for Neuron {
             tau: Register::new(10.0),
             v_mem: Register::new(-70.0),
             last_received_pulse_step: 0,
-            weights: Vec::new(),
-            internal_weights: Vec::new(),
+            weights: Arc::default(),
+            synapse_sources: None,
+            receptive_field: None,
+            internal_weights: Arc::default(),
+            bias: Register::new(0.0),
+            delays: Arc::default(),
+            delayed_pulses: Vec::new(),
             add_reg: Register::new(0.0),
             mul_reg: Register::new(0.0),
             cmp_reg: Register::new(0.0),
             div_reg: Register::new(0.0),
+            model: None,
+            homeostasis_state: HomeostasisState::default(),
         }
     }
 }
@@ -63,25 +110,201 @@ impl Neuron {
             tau: Register::new(tau),
             v_mem: Register::new(v_rest),
             last_received_pulse_step: 0,
-            weights: Vec::new(),
-            internal_weights: Vec::new(),
+            weights: Arc::default(),
+            synapse_sources: None,
+            receptive_field: None,
+            internal_weights: Arc::default(),
+            bias: Register::new(0.0),
+            delays: Arc::default(),
+            delayed_pulses: Vec::new(),
             add_reg: Register::new(0.0),
             mul_reg: Register::new(0.0),
             cmp_reg: Register::new(0.0),
             div_reg: Register::new(0.0),
+            model: None,
+            homeostasis_state: HomeostasisState::default(),
+        }
+    }
+
+    /// Restore the state of the Neuron to the one it has before any simulation: the
+    /// membrane potential is set to v_rest, the registers of the arithmetic units are
+    /// cleared and no pulses are waiting on delayed synapses. Parameters (including the
+    /// threshold adapted by homeostasis) and damages are left unchanged.
+    pub fn reset_state(&mut self) {
+        self.v_mem.write_value(self.v_rest.stored_value());
+        self.last_received_pulse_step = 0;
+        self.add_reg.write_value(0.0);
+        self.mul_reg.write_value(0.0);
+        self.cmp_reg.write_value(0.0);
+        self.div_reg.write_value(0.0);
+        self.delayed_pulses.clear();
+    }
+
+    /// Check whether the Neuron is in the same state as 'other', i.e. whether all their
+    /// Registers store the same values (damages are not considered) and they have the
+    /// same pulses waiting on delayed synapses and the same homeostasis state. Neurons in
+    /// the same state behave in the same way from then on, if they are not damaged.
+    pub fn has_same_state(&self, other: &Neuron) -> bool {
+        let same_values = |registers: &[Register], other_registers: &[Register]| {
+            registers.len() == other_registers.len()
+                && registers
+                    .iter()
+                    .zip(other_registers)
+                    .all(|(register, other_register)| {
+                        register.stored_value().to_bits() == other_register.stored_value().to_bits()
+                    })
+        };
+
+        // shared synapse Registers are the same without comparing them
+        let same_shared_values =
+            |registers: &Arc<Vec<Register>>, other_registers: &Arc<Vec<Register>>| {
+                Arc::ptr_eq(registers, other_registers) || same_values(registers, other_registers)
+            };
+
+        same_values(
+            &[
+                self.v_th,
+                self.v_rest,
+                self.v_reset,
+                self.tau,
+                self.v_mem,
+                self.bias,
+                self.add_reg,
+                self.mul_reg,
+                self.cmp_reg,
+                self.div_reg,
+            ],
+            &[
+                other.v_th,
+                other.v_rest,
+                other.v_reset,
+                other.tau,
+                other.v_mem,
+                other.bias,
+                other.add_reg,
+                other.mul_reg,
+                other.cmp_reg,
+                other.div_reg,
+            ],
+        ) && same_shared_values(&self.weights, &other.weights)
+            && self.synapse_sources == other.synapse_sources
+            && self.receptive_field == other.receptive_field
+            && same_shared_values(&self.internal_weights, &other.internal_weights)
+            && same_shared_values(&self.delays, &other.delays)
+            && self.last_received_pulse_step == other.last_received_pulse_step
+            && self.delayed_pulses == other.delayed_pulses
+            && self.homeostasis_state == other.homeostasis_state
+    }
+
+    /// Get a reference to the specified Register of the Neuron, if it exists
+    pub fn register(&self, register: NeuronRegister) -> Option<&Register> {
+        match register {
+            NeuronRegister::Weight(index) => self.weights.get(index),
+            NeuronRegister::InternalWeight(index) => self.internal_weights.get(index),
+            NeuronRegister::Delay(index) => self.delays.get(index),
+            NeuronRegister::Threshold => Some(&self.v_th),
+            NeuronRegister::MembranePotential => Some(&self.v_mem),
+            NeuronRegister::ResetPotential => Some(&self.v_reset),
+            NeuronRegister::PotentialAtRest => Some(&self.v_rest),
+            NeuronRegister::Bias => Some(&self.bias),
+            NeuronRegister::Comparator => Some(&self.cmp_reg),
+            NeuronRegister::Adder => Some(&self.add_reg),
+            NeuronRegister::Multiplier => Some(&self.mul_reg),
+            NeuronRegister::Divider => Some(&self.div_reg),
         }
     }
 
+    /// Get a mutable reference to the specified Register of the Neuron, if it exists.
+    /// If it is a synapse Register shared with other Neurons, the Vec it belongs to
+    /// is copied first, so that the other Neurons are not affected.
+    pub fn register_mut(&mut self, register: NeuronRegister) -> Option<&mut Register> {
+        match register {
+            NeuronRegister::Weight(index) => Self::shared_register_mut(&mut self.weights, index),
+            NeuronRegister::InternalWeight(index) => {
+                Self::shared_register_mut(&mut self.internal_weights, index)
+            }
+            NeuronRegister::Delay(index) => Self::shared_register_mut(&mut self.delays, index),
+            NeuronRegister::Threshold => Some(&mut self.v_th),
+            NeuronRegister::MembranePotential => Some(&mut self.v_mem),
+            NeuronRegister::ResetPotential => Some(&mut self.v_reset),
+            NeuronRegister::PotentialAtRest => Some(&mut self.v_rest),
+            NeuronRegister::Bias => Some(&mut self.bias),
+            NeuronRegister::Comparator => Some(&mut self.cmp_reg),
+            NeuronRegister::Adder => Some(&mut self.add_reg),
+            NeuronRegister::Multiplier => Some(&mut self.mul_reg),
+            NeuronRegister::Divider => Some(&mut self.div_reg),
+        }
+    }
+
+    /// Get a mutable reference to a Register of a shared Vec, copying the Vec if it is
+    /// shared (the Vec is not copied if the Register does not exist)
+    fn shared_register_mut(
+        registers: &mut Arc<Vec<Register>>,
+        index: usize,
+    ) -> Option<&mut Register> {
+        if index >= registers.len() {
+            return None;
+        }
+        Arc::make_mut(registers).get_mut(index)
+    }
+
     /// Set weights for synapses external to the current layer. Cell 'i' in the weights Vec
     /// represents the weight assigned to the synapse going from the Neuron with index
     /// 'i' in the previous layer to the current Neuron.
     /// 'Weights' Vec must have as many elements as the number of Neurons in the previous
     /// layer.
     pub fn set_weights(&mut self, weights: Vec<f64>) {
-        self.weights = weights
-            .into_iter()
-            .map(|w| Register::new(w))
-            .collect::<Vec<Register>>();
+        self.weights = Arc::new(
+            weights
+                .into_iter()
+                .map(|w| Register::new(w))
+                .collect::<Vec<Register>>(),
+        );
+        self.synapse_sources = None;
+        self.receptive_field = None;
+    }
+
+    /// Connect the Neuron sparsely to the previous layer: it only has the synapses in
+    /// 'synapses', each one given as the index of the Neuron of the previous layer it comes
+    /// from and its weight. Synapses are stored in increasing order of source, which is
+    /// the order of their Registers in 'weights' (and 'delays'). There must be at most one
+    /// synapse from each source.
+    pub fn set_synapses(&mut self, mut synapses: Vec<(usize, f64)>) {
+        synapses.sort_by_key(|(source_index, _)| *source_index);
+        assert!(
+            synapses.windows(2).all(|pair| pair[0].0 != pair[1].0),
+            "a Neuron cannot have more synapses from the same source"
+        );
+
+        self.synapse_sources = Some(Arc::new(
+            synapses
+                .iter()
+                .map(|(source_index, _)| *source_index)
+                .collect(),
+        ));
+        self.weights = Arc::new(
+            synapses
+                .into_iter()
+                .map(|(_, weight)| Register::new(weight))
+                .collect(),
+        );
+        self.receptive_field = None;
+    }
+
+    /// Get the index, in 'weights', of the synapse going from the Neuron with index
+    /// 'source_index' in the previous layer to the current Neuron, if it exists. If the
+    /// Neuron is not sparsely connected, it is the index of the source itself; if it
+    /// belongs to a convolutional layer, it is the index of the kernel weight.
+    pub fn synapse_index(&self, source_index: usize) -> Option<usize> {
+        if let Some(receptive_field) = &self.receptive_field {
+            return receptive_field
+                .convolution
+                .kernel_index(receptive_field.output_index, source_index);
+        }
+        match &self.synapse_sources {
+            Some(synapse_sources) => synapse_sources.binary_search(&source_index).ok(),
+            None => Some(source_index),
+        }
     }
 
     /// Set weights for synapses internal to the current layer. Cell 'i' in the internal_weights
@@ -90,43 +313,172 @@ impl Neuron {
     /// internal_weights Vec must have as many elements as the number of Neurons in the current
     /// layer. The weight of the internal synapse going from a node to itself should be set to 0.0.
     pub fn set_internal_weights(&mut self, internal_weights: Vec<f64>) {
-        self.internal_weights = internal_weights
-            .into_iter()
-            .map(|w| Register::new(w))
-            .collect::<Vec<Register>>();
+        self.internal_weights = Arc::new(
+            internal_weights
+                .into_iter()
+                .map(|w| Register::new(w))
+                .collect::<Vec<Register>>(),
+        );
+    }
+
+    /// Set the bias of the Neuron, i.e. a constant contribution added to the Membrane
+    /// Potential on every time step (as the bias term of a converted ANN layer), even
+    /// when the Neuron receives no Pulses (see Neuron::receives_input).
+    pub fn set_bias(&mut self, bias: f64) {
+        self.bias = Register::new(bias);
+    }
+
+    /// Set delays (in time steps) for synapses external to the current layer. Cell 'i' in
+    /// the delays Vec represents the delay of the synapse going from the Neuron with index
+    /// 'i' in the previous layer to the current Neuron: a pulse produced at time step 't'
+    /// is delivered to the current Neuron at time step 't + delay'.
+    /// 'Delays' Vec must have as many elements as the 'Weights' one.
+    pub fn set_delays(&mut self, delays: Vec<usize>) {
+        self.delays = Arc::new(
+            delays
+                .into_iter()
+                .map(|d| Register::new(d as f64))
+                .collect::<Vec<Register>>(),
+        );
+    }
+
+    /// Buffer the Pulses received at the current time step on delayed synapses and return
+    /// the sources of all Pulses which must be delivered to the Neuron at the current time
+    /// step: those received on synapses with no delay and those buffered in previous time
+    /// steps whose delay has expired.
+    /// Delays are read from their Registers when the Pulse is received, so a damaged
+    /// delay line changes the time step when the Pulse is delivered.
+    /// A sparsely connected Neuron ignores the Pulses coming from Neurons it has no
+    /// synapse from, a Neuron of a convolutional layer those from outside its window.
+    pub fn receive_pulses(&mut self, pulse_sources: &[usize], time_step: usize) -> Vec<usize> {
+        let connected_pulse_sources =
+            if self.synapse_sources.is_some() || self.receptive_field.is_some() {
+                pulse_sources
+                    .iter()
+                    .copied()
+                    .filter(|source_index| self.synapse_index(*source_index).is_some())
+                    .collect()
+            } else {
+                pulse_sources.to_vec()
+            };
+
+        // no delay lines: all pulses are delivered immediately
+        if self.delays.is_empty() && self.delayed_pulses.is_empty() {
+            return connected_pulse_sources;
+        }
+
+        let mut due_pulse_sources = Vec::new();
+        for source_index in connected_pulse_sources {
+            // reading the delay of the synapse: a corrupted value is interpreted as the
+            // closest valid number of time steps
+            let delay = match self
+                .synapse_index(source_index)
+                .and_then(|synapse_index| self.delays.get(synapse_index))
+            {
+                Some(delay_reg) => delay_reg.read_value(Some(time_step)).unwrap() as usize,
+                None => 0,
+            };
+            if delay == 0 {
+                due_pulse_sources.push(source_index);
+            } else {
+                self.delayed_pulses.push(DelayedPulse {
+                    source_index,
+                    delivery_step: time_step.saturating_add(delay),
+                });
+            }
+        }
+
+        // collecting buffered pulses which must be delivered at the current time step
+        self.delayed_pulses.retain(|pulse| {
+            if pulse.delivery_step <= time_step {
+                due_pulse_sources.push(pulse.source_index);
+                return false;
+            }
+            true
+        });
+
+        due_pulse_sources
+    }
+
+    /// Check whether the Neuron has to integrate its input at the current time step, given
+    /// the sources of the Pulses delivered to it (see receive_pulses): this happens when it
+    /// receives some Pulses or when its bias is not 0, since the bias is integrated on
+    /// every time step.
+    pub fn receives_input(&self, due_pulse_sources: &[usize], time_step: usize) -> bool {
+        !due_pulse_sources.is_empty() || self.bias.read_value(Some(time_step)).unwrap() != 0.0
     }
 
     /// simulate the reception of a series of Pulses on the input synapses for the Neuron
     /// which causes a change in the Membrane Potential. If this potential goes beyond the
     /// threshold (v_th), then the function returns true, simulating the emission of a
     /// Pulse, otherwise it returns false.
+    /// If a NoiseSource is provided, noise is added to the Membrane Potential and, if the
+    /// noise model uses escape noise, firing is decided stochastically.
+    /// When the Neuron fires, its Membrane Potential is reset according to 'reset_mode'.
     pub fn feed_pulses(
         &mut self,
         pulse_sources: &Vec<usize>,
         time_step: usize,
         time_step_duration_ms: f64,
-        neuron_model: NeuronModel,
+        neuron_model: &dyn NeuronDynamics,
+        mut noise: Option<&mut NoiseSource>,
+        reset_mode: ResetMode,
     ) -> bool {
+        self.integrate_pulses(
+            pulse_sources,
+            time_step,
+            time_step_duration_ms,
+            neuron_model,
+            noise.as_deref_mut(),
+        );
+
+        //comparing v_mem to threshold
+        if self.fires(time_step, neuron_model, noise) {
+            // The Neuron fires: Membrane potential must be reset
+            neuron_model.reset(self, reset_mode, time_step);
+            return true;
+        }
+
+        false
+    }
+
+    /// simulate the reception of a series of Pulses on the input synapses for the Neuron,
+    /// updating the Membrane Potential without checking whether the Neuron fires.
+    pub fn integrate_pulses(
+        &mut self,
+        pulse_sources: &Vec<usize>,
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_model: &dyn NeuronDynamics,
+        noise: Option<&mut NoiseSource>,
+    ) {
         self.update_membrane_potential(
             pulse_sources,
             time_step,
             time_step_duration_ms,
             neuron_model,
+            noise,
             PulseContributionMode::Excitatory,
         );
 
         // updating last_received_pulse_step
         self.last_received_pulse_step = time_step;
+    }
 
-        //comparing v_mem to threshold
-        Register::cmp(self.v_mem, self.v_th, &mut self.cmp_reg, time_step);
-        if self.cmp_reg.read_value(Some(time_step)).unwrap() >= 0.0 {
-            // The Neuron fires: Membrane potential must be reset
-            self.v_reset.copy_to(&mut self.v_mem, time_step);
-            return true;
+    /// check whether the Neuron fires with its current Membrane Potential: the check is
+    /// performed by the neuron model or, if the noise model uses escape noise, stochastically.
+    pub fn fires(
+        &mut self,
+        time_step: usize,
+        neuron_model: &dyn NeuronDynamics,
+        noise: Option<&mut NoiseSource>,
+    ) -> bool {
+        match noise {
+            Some(noise) if noise.model.escape_noise_width.is_some() => {
+                self.escape_noise_fires(noise, time_step)
+            }
+            _ => neuron_model.fires(self, time_step),
         }
-
-        return false;
     }
 
     /// simulate loss of membrane potential for a Neuron when other Neurons of the same
@@ -137,13 +489,15 @@ impl Neuron {
         pulse_sources: &Vec<usize>,
         time_step: usize,
         time_step_duration_ms: f64,
-        neuron_model: NeuronModel,
+        neuron_model: &dyn NeuronDynamics,
+        noise: Option<&mut NoiseSource>,
     ) {
         self.update_membrane_potential(
             pulse_sources,
             time_step,
             time_step_duration_ms,
             neuron_model,
+            noise,
             PulseContributionMode::Inhibitive,
         );
 
@@ -151,14 +505,79 @@ impl Neuron {
         self.last_received_pulse_step = time_step;
     }
 
+    /// simulate loss of membrane potential for a Neuron when other Neurons of the same
+    /// layer 'fire' at the preceding time step, using the same inhibition strength for
+    /// all of them. 'pulse_sources' must not contain the index of the current Neuron, which
+    /// is not inhibited by its own pulses.
+    pub fn inhibite_uniformly_after_pulses_emission(
+        &mut self,
+        pulse_sources: &Vec<usize>,
+        strength: f64,
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_model: &dyn NeuronDynamics,
+        noise: Option<&mut NoiseSource>,
+    ) {
+        self.update_membrane_potential(
+            pulse_sources,
+            time_step,
+            time_step_duration_ms,
+            neuron_model,
+            noise,
+            PulseContributionMode::UniformInhibitive { strength },
+        );
+
+        // updating last_received_pulse_step
+        self.last_received_pulse_step = time_step;
+    }
+
+    /// update the firing activity measured for homeostasis with the outcome of the current
+    /// time step ('fired' is true if the Neuron emitted a pulse). At the end of each window
+    /// the threshold is moved, using the adder, so that the firing rate approaches the
+    /// target one
+    pub fn regulate_threshold(&mut self, fired: bool, homeostasis: &Homeostasis, time_step: usize) {
+        self.homeostasis_state.elapsed_steps += 1;
+        if fired {
+            self.homeostasis_state.spike_count += 1;
+        }
+
+        if self.homeostasis_state.elapsed_steps >= homeostasis.window {
+            // computing the firing rate measured during the window
+            let firing_rate = self.homeostasis_state.spike_count as f64
+                / self.homeostasis_state.elapsed_steps as f64;
+
+            // raising the threshold if the Neuron fires too often, lowering it otherwise
+            let threshold_change =
+                Register::new(homeostasis.learning_rate * (firing_rate - homeostasis.target_rate));
+            Register::add(self.v_th, threshold_change, &mut self.add_reg, time_step);
+            self.add_reg.copy_to(&mut self.v_th, time_step);
+
+            // starting a new window
+            self.homeostasis_state = HomeostasisState::default();
+        }
+    }
+
+    /// decide whether the Neuron fires using the escape noise model: the distance between
+    /// the membrane potential and the threshold is computed by the comparator, then the
+    /// Neuron fires with a probability which grows with that distance
+    fn escape_noise_fires(&mut self, noise: &mut NoiseSource, time_step: usize) -> bool {
+        Register::cmp(self.v_mem, self.v_th, &mut self.cmp_reg, time_step);
+        let distance_from_threshold = self.cmp_reg.read_value(Some(time_step)).unwrap();
+        let width = noise.model.escape_noise_width.unwrap();
+        noise.escape_fires(distance_from_threshold, width)
+    }
+
     ///compute pulse contribution to v_mem, based on the stored weights
     fn get_pulses_contribution(&self, pulse_sources: &Vec<usize>, time_step: usize) -> Register {
         let mut add_reg = self.add_reg;
         add_reg.write_value(0.0);
         for source_index in pulse_sources {
+            let synapse_index = self
+                .synapse_index(*source_index)
+                .expect("the Neuron has no synapse from the source of the pulse");
             Register::add(
                 add_reg,
-                self.weights[*source_index],
+                self.weights[synapse_index],
                 &mut add_reg,
                 time_step,
             );
@@ -167,6 +586,23 @@ impl Neuron {
         return add_reg;
     }
 
+    ///compute inhibitive contribution to v_mem, subtracting the same strength for each pulse
+    ///emitted by the other Neurons of the layer
+    fn get_uniform_inhibitive_contribution(
+        &self,
+        pulse_sources: &Vec<usize>,
+        strength: f64,
+        time_step: usize,
+    ) -> Register {
+        let mut add_reg = self.add_reg;
+        add_reg.write_value(0.0);
+        for _ in pulse_sources {
+            Register::sub(add_reg, Register::new(strength), &mut add_reg, time_step);
+        }
+
+        add_reg
+    }
+
     ///compute inhibitive contribution to v_mem, based on the stored internal weights
     fn get_inhibitive_contribution(
         &self,
@@ -193,82 +629,124 @@ impl Neuron {
         pulse_sources: &Vec<usize>,
         time_step: usize,
         time_step_duration_ms: f64,
-        neuron_model: NeuronModel,
+        neuron_model: &dyn NeuronDynamics,
+        noise: Option<&mut NoiseSource>,
         pulse_contribution_mode: PulseContributionMode,
     ) {
         // computing v_mem contribution due to pulses
         let pulses_contribution = match pulse_contribution_mode {
             PulseContributionMode::Excitatory => {
-                self.get_pulses_contribution(pulse_sources, time_step)
+                let mut contribution = self.get_pulses_contribution(pulse_sources, time_step);
+                // adding the bias current to the contribution of the received pulses
+                let weighted_pulses = contribution;
+                Register::add(weighted_pulses, self.bias, &mut contribution, time_step);
+                contribution
             }
             PulseContributionMode::Inhibitive => {
                 self.get_inhibitive_contribution(pulse_sources, time_step)
             }
+            PulseContributionMode::UniformInhibitive { strength } => {
+                self.get_uniform_inhibitive_contribution(pulse_sources, strength, time_step)
+            }
         };
 
         // computing new Membrane Potential
-
-        let mut pulses_contrib_reg = Register::new(0.0);
-        Register::add(
-            self.v_mem,
+        neuron_model.update_membrane_potential(
+            self,
             pulses_contribution,
-            &mut self.add_reg,
             time_step,
+            time_step_duration_ms,
         );
-        self.add_reg.copy_to(&mut pulses_contrib_reg, time_step);
-
-        match neuron_model {
-            NeuronModel::LeakyIntegrateAndFire => {
-                // computing v_mem - v_rest
-                let mut vm_vr = Register::new(0.0);
-                Register::sub(self.v_mem, self.v_rest, &mut self.add_reg, time_step);
-                self.add_reg.copy_to(&mut vm_vr, time_step);
-
-                // computing last_received_pulse_step - time_step
-                let diff_steps =
-                    Register::new(self.last_received_pulse_step as f64 - time_step as f64);
-
-                // computing exp argument
-                let mut exp_arg = Register::new(0.0);
-                Register::mult(
-                    diff_steps,
-                    Register::new(time_step_duration_ms),
-                    &mut self.mul_reg,
-                    time_step,
-                );
-                Register::div(self.mul_reg, self.tau, &mut self.div_reg, time_step);
-                self.div_reg.copy_to(&mut exp_arg, time_step);
-
-                // performing exp
-                let exp_res = Register::new(exp_arg.read_value(Some(time_step)).unwrap().exp());
-
-                // computing exp * (v_mem - v_rest)
-                let mut decay_part = Register::new(0.0);
-                Register::mult(exp_res, vm_vr, &mut self.mul_reg, time_step);
-                self.mul_reg.copy_to(&mut decay_part, time_step);
-
-                // computing decay_part + pulses_contrib_reg
-                Register::add(decay_part, pulses_contrib_reg, &mut self.add_reg, time_step);
+
+        // adding membrane noise, if any, using the adder
+        if let Some(noise) = noise {
+            if noise.model.membrane_noise_std != 0.0 {
+                let noise_reg = Register::new(noise.membrane_noise());
+                Register::add(self.v_mem, noise_reg, &mut self.add_reg, time_step);
                 self.add_reg.copy_to(&mut self.v_mem, time_step);
             }
-            NeuronModel::IntegrateAndFire => {
-                pulses_contrib_reg.copy_to(&mut self.v_mem, time_step);
-            }
         }
     }
 }
 
 /// A Message can be sent from a layer to another layer in order to transfer
 /// pulses or control messages to achieve synchronization between adjacent layers.
+/// Each Message carries the time step it belongs to, so that the receiver can
+/// detect if it is out of sync with the sender.
 pub enum Message {
-    // a pulse keeps the index of the Neuron which produced it
-    Pulse(usize),
-    // notify the following layer that all pulses have been delivered for that time step
-    GoAhead,
+    // a pulse keeps the time step when it was produced and the index of the Neuron
+    // which produced it
+    Pulse {
+        time_step: usize,
+        source_index: usize,
+    },
+    // notify the following layer that all pulses have been delivered for that time step.
+    // It is sent for every time step, even if no pulses have been produced
+    GoAhead {
+        time_step: usize,
+    },
+}
+
+/// A DelayedPulse is a Pulse received by a Neuron on a delayed synapse, which
+/// is kept in the Neuron buffer until its delivery time step.
+#[derive(Clone, Copy, PartialEq)]
+pub struct DelayedPulse {
+    pub source_index: usize, // index of the Neuron of the previous layer which produced the Pulse
+    pub delivery_step: usize, // time step when the Pulse must be delivered
+}
+
+/// NeuronRegister identifies one of the Registers of a Neuron: for Registers
+/// belonging to a Vec, the index inside the Vec is specified (for the weights and
+/// delays of a sparsely connected Neuron, it is the index of the synapse, not of
+/// its source)
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum NeuronRegister {
+    Weight(usize),
+    InternalWeight(usize),
+    Delay(usize),
+    Threshold,
+    MembranePotential,
+    ResetPotential,
+    PotentialAtRest,
+    Bias,
+    Comparator,
+    Adder,
+    Multiplier,
+    Divider,
+}
+
+impl NeuronRegister {
+    /// Get the name of the Register, as used in the fields of Neuron
+    pub fn name(&self) -> String {
+        match self {
+            NeuronRegister::Weight(index) => format!("weights[{index}]"),
+            NeuronRegister::InternalWeight(index) => format!("internal_weights[{index}]"),
+            NeuronRegister::Delay(index) => format!("delays[{index}]"),
+            NeuronRegister::Threshold => String::from("v_th"),
+            NeuronRegister::MembranePotential => String::from("v_mem"),
+            NeuronRegister::ResetPotential => String::from("v_reset"),
+            NeuronRegister::PotentialAtRest => String::from("v_rest"),
+            NeuronRegister::Bias => String::from("bias"),
+            NeuronRegister::Comparator => String::from("cmp_reg"),
+            NeuronRegister::Adder => String::from("add_reg"),
+            NeuronRegister::Multiplier => String::from("mul_reg"),
+            NeuronRegister::Divider => String::from("div_reg"),
+        }
+    }
+}
+
+/// HomeostasisState keeps the firing activity of a Neuron during the current
+/// homeostasis window
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct HomeostasisState {
+    pub spike_count: usize,   // pulses emitted during the current window
+    pub elapsed_steps: usize, // time steps elapsed since the beginning of the window
 }
 
 /// Specify if neuron is receiving pulses on excitatory or inhibitive layer
 enum PulseContributionMode {
     Excitatory,
     Inhibitive,
+    // inhibition with the same strength from all other Neurons of the layer
+    UniformInhibitive { strength: f64 },
 }
@@ -0,0 +1,75 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// The NoiseModel struct describes the intrinsic noise of the Neurons of the
+/// network, which can be compared with the effect of hardware damages:
+///
+/// - membrane_noise_std: standard deviation (mV) of the gaussian noise added to
+///   the membrane potential at each update. If it is 0.0, no noise is added.
+/// - escape_noise_width: if set, Neurons fire stochastically instead of using a
+///   hard threshold: the probability of firing is a sigmoid of (v_mem - v_th),
+///   and this parameter (mV) controls how smooth the sigmoid is.
+/// - seed: seed of the random number generator, so that runs are reproducible.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NoiseModel {
+    #[serde(default)]
+    pub membrane_noise_std: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escape_noise_width: Option<f64>,
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl NoiseModel {
+    /// Check that the parameters of the noise model are valid: the escape noise width,
+    /// if set, must be greater than 0, since firing probabilities are computed dividing
+    /// by it. Panics otherwise.
+    pub fn assert_valid(&self) {
+        assert!(
+            self.escape_noise_width.is_none_or(|width| width > 0.0),
+            "the escape noise width must be greater than 0"
+        );
+    }
+}
+
+/// A NoiseSource generates the random values needed by a NoiseModel. Each layer
+/// owns a NoiseSource seeded from the NoiseModel seed and the layer index, so
+/// that the sequence of values does not depend on thread scheduling.
+#[derive(Clone, PartialEq)]
+pub struct NoiseSource {
+    pub model: NoiseModel,
+    rng: StdRng,
+}
+
+impl NoiseSource {
+    /// Create the NoiseSource for the layer having index 'layer_index'.
+    ///
+    /// Panics if the noise model is not valid (see NoiseModel::assert_valid).
+    pub fn new(model: NoiseModel, layer_index: usize) -> Self {
+        model.assert_valid();
+        // mixing the layer index into the seed, so that layers get different streams
+        let seed = model.seed ^ (layer_index as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        NoiseSource {
+            model,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Sample the noise to be added to the membrane potential, drawn from a gaussian
+    /// distribution having mean 0.0 and standard deviation membrane_noise_std
+    pub fn membrane_noise(&mut self) -> f64 {
+        // Box-Muller transform: u1 is taken in (0, 1] so that its logarithm is finite
+        let u1 = 1.0 - self.rng.gen::<f64>();
+        let u2 = self.rng.gen::<f64>();
+        let standard_normal = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+        standard_normal * self.model.membrane_noise_std
+    }
+
+    /// Decide whether a Neuron fires according to the escape noise model, given the
+    /// distance of its membrane potential from the threshold (v_mem - v_th)
+    pub fn escape_fires(&mut self, distance_from_threshold: f64, width: f64) -> bool {
+        let firing_probability = 1.0 / (1.0 + (-distance_from_threshold / width).exp());
+        self.rng.gen::<f64>() < firing_probability
+    }
+}
@@ -0,0 +1,223 @@
+use crate::network::layer::Layer;
+use crate::network::neuron::NeuronRegister;
+use crate::network::{Fault, RegisterAddress};
+use crate::register::{Damage, Register};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A FaultOverlay collects the Damages applied to the Registers of a network, by layer.
+/// Damages are not stored inside the Registers of the network, which keep the values of
+/// the network without damages. The engines consult the overlay while simulating each
+/// layer: the Damages affecting the layer are applied to its Registers only during the
+/// time step (see arm) and removed right afterwards. Synapse Registers are damaged in a
+/// private copy of their Vec, which is dropped when the Faults are removed, so that the
+/// Vecs of the network stay shared read-only (see Neuron).
+#[derive(Clone, Default)]
+pub struct FaultOverlay {
+    layers: HashMap<usize, Vec<Fault>>, // Faults affecting each layer, one per Register
+}
+
+impl FaultOverlay {
+    /// Create an empty overlay
+    pub fn new() -> Self {
+        FaultOverlay {
+            layers: HashMap::new(),
+        }
+    }
+
+    /// Add a Fault to the overlay, replacing the Damage previously applied to the same
+    /// Register, if any
+    pub fn insert(&mut self, fault: Fault) {
+        let layer_faults = self.layers.entry(fault.address.layer).or_default();
+        match layer_faults
+            .iter_mut()
+            .find(|layer_fault| layer_fault.address == fault.address)
+        {
+            Some(layer_fault) => layer_fault.damage = fault.damage,
+            None => layer_faults.push(fault),
+        }
+    }
+
+    /// Remove the Damage applied to the Register at the specified address, returning it
+    pub fn remove(&mut self, address: RegisterAddress) -> Option<Damage> {
+        let layer_faults = self.layers.get_mut(&address.layer)?;
+        let index = layer_faults
+            .iter()
+            .position(|fault| fault.address == address)?;
+        let fault = layer_faults.remove(index);
+        if layer_faults.is_empty() {
+            self.layers.remove(&address.layer);
+        }
+        Some(fault.damage)
+    }
+
+    /// Get the Damage applied to the Register at the specified address, if any
+    pub fn damage(&self, address: RegisterAddress) -> Option<Damage> {
+        self.of_layer(address.layer)
+            .iter()
+            .find(|fault| fault.address == address)
+            .map(|fault| fault.damage)
+    }
+
+    /// Get the number of damaged Registers
+    pub fn len(&self) -> usize {
+        self.layers.values().map(Vec::len).sum()
+    }
+
+    /// Check whether no Register is damaged
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Get all the Faults of the overlay
+    pub fn faults(&self) -> Vec<Fault> {
+        self.layers.values().flatten().copied().collect()
+    }
+
+    /// Get the Faults affecting the Registers of a layer
+    pub fn of_layer(&self, layer: usize) -> &[Fault] {
+        self.layers.get(&layer).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// The Damages the Registers of a layer had before its Faults were applied by arm, which
+/// must be given back to disarm
+pub(crate) struct ArmedFaults {
+    previous_damages: Vec<Option<Damage>>, // None for the Faults applied to synapse Registers
+    synapse_vecs: Vec<(usize, SynapseVecs)>, // synapse Vecs of the Neurons damaged by a Fault
+    damaged_kernel: Option<Arc<Vec<Register>>>, // copy of the kernel shared while armed
+}
+
+/// The Vecs of synapse Registers of a Neuron (weights, internal weights and delays)
+type SynapseVecs = [Arc<Vec<Register>>; 3];
+
+/// Apply the Faults affecting a layer ('layer_faults', see FaultOverlay::of_layer) to its
+/// Registers, so that they are taken into account while the layer is simulated.
+/// Synapse Registers are damaged in a copy of their Vec (see Neuron::register_mut), while
+/// the original Vecs are kept, to be given back to the Neuron by disarm.
+/// A Fault on a weight of the kernel of a convolutional layer (see Layer::set_kernel) is
+/// applied to a copy of the kernel shared by all the Neurons using it, so that it affects
+/// every output position.
+pub(crate) fn arm(layer: &mut Layer, layer_faults: &[Fault]) -> ArmedFaults {
+    let kernel_faults = layer_faults
+        .iter()
+        .map(|fault| is_kernel_fault(layer, fault))
+        .collect::<Vec<bool>>();
+    let mut damaged_kernel = layer
+        .kernel
+        .as_ref()
+        .filter(|_| kernel_faults.contains(&true))
+        .map(|kernel| (*kernel.weights).clone());
+
+    // the synapse Vecs of the damaged Neurons are saved before any of them is copied
+    let mut synapse_vecs: Vec<(usize, SynapseVecs)> = Vec::new();
+    for (fault, is_kernel_fault) in layer_faults.iter().zip(&kernel_faults) {
+        let neuron_nr = fault.address.neuron;
+        if *is_kernel_fault
+            || !is_synapse_register(fault.address.register)
+            || synapse_vecs
+                .iter()
+                .any(|(saved_nr, _)| *saved_nr == neuron_nr)
+        {
+            continue;
+        }
+        if let Some(neuron) = layer.neurons.get(neuron_nr) {
+            synapse_vecs.push((
+                neuron_nr,
+                [
+                    neuron.weights.clone(),
+                    neuron.internal_weights.clone(),
+                    neuron.delays.clone(),
+                ],
+            ));
+        }
+    }
+
+    let previous_damages = layer_faults
+        .iter()
+        .zip(&kernel_faults)
+        .map(|(fault, is_kernel_fault)| {
+            let register = match (&mut damaged_kernel, fault.address.register) {
+                (Some(damaged_kernel), NeuronRegister::Weight(index)) if *is_kernel_fault => {
+                    damaged_kernel.get_mut(index)
+                }
+                _ => layer
+                    .neurons
+                    .get_mut(fault.address.neuron)
+                    .and_then(|neuron| neuron.register_mut(fault.address.register)),
+            }
+            .expect("the overlay refers to a Register which does not exist");
+            let previous_damage = register.damage();
+            register.apply_damage(fault.damage);
+            (!is_synapse_register(fault.address.register)).then_some(previous_damage)
+        })
+        .collect();
+
+    let damaged_kernel = damaged_kernel.map(Arc::new);
+    if let (Some(kernel), Some(damaged_kernel)) = (&layer.kernel, &damaged_kernel) {
+        for neuron in layer.neurons.iter_mut() {
+            if Arc::ptr_eq(&neuron.weights, &kernel.weights) {
+                neuron.weights = damaged_kernel.clone();
+            }
+        }
+    }
+
+    ArmedFaults {
+        previous_damages,
+        synapse_vecs,
+        damaged_kernel,
+    }
+}
+
+/// Remove the Faults applied by 'arm', giving back to the Registers the Damages they had
+/// before and to the damaged Neurons their original synapse Vecs ('armed_faults')
+pub(crate) fn disarm(layer: &mut Layer, layer_faults: &[Fault], armed_faults: ArmedFaults) {
+    for (neuron_nr, [weights, internal_weights, delays]) in armed_faults.synapse_vecs {
+        let neuron = &mut layer.neurons[neuron_nr];
+        neuron.weights = weights;
+        neuron.internal_weights = internal_weights;
+        neuron.delays = delays;
+    }
+    if let (Some(kernel), Some(damaged_kernel)) = (&layer.kernel, &armed_faults.damaged_kernel) {
+        for neuron in layer.neurons.iter_mut() {
+            if Arc::ptr_eq(&neuron.weights, damaged_kernel) {
+                neuron.weights = kernel.weights.clone();
+            }
+        }
+    }
+
+    // Faults are disarmed in reverse order, in case more of them refer to the same Register
+    for (fault, previous_damage) in layer_faults.iter().zip(armed_faults.previous_damages).rev() {
+        let Some(previous_damage) = previous_damage else {
+            continue;
+        };
+        if let Some(register) = layer
+            .neurons
+            .get_mut(fault.address.neuron)
+            .and_then(|neuron| neuron.register_mut(fault.address.register))
+        {
+            register.apply_damage(previous_damage);
+        }
+    }
+}
+
+/// Check whether a Fault affects a weight of the kernel shared by the Neurons of a
+/// convolutional layer
+pub(crate) fn is_kernel_fault(layer: &Layer, fault: &Fault) -> bool {
+    let Some(kernel) = &layer.kernel else {
+        return false;
+    };
+    matches!(fault.address.register, NeuronRegister::Weight(_))
+        && layer
+            .neurons
+            .get(fault.address.neuron)
+            .is_some_and(|neuron| Arc::ptr_eq(&neuron.weights, &kernel.weights))
+}
+
+/// Check whether a Register belongs to the synapse Vecs of a Neuron
+fn is_synapse_register(register: NeuronRegister) -> bool {
+    matches!(
+        register,
+        NeuronRegister::Weight(_) | NeuronRegister::InternalWeight(_) | NeuronRegister::Delay(_)
+    )
+}
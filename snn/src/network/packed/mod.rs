@@ -0,0 +1,938 @@
+use crate::network::convolution::ReceptiveField;
+use crate::network::dynamics::NeuronDynamics;
+use crate::network::layer::Layer;
+use crate::network::neuron::{DelayedPulse, HomeostasisState, Neuron, NeuronRegister};
+use crate::network::noise::NoiseSource;
+use crate::network::{Fault, Homeostasis, LateralInhibition, NeuronModel, ResetMode};
+use crate::register::{Damage, Register};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+/// A PackedLayer holds the same Neurons as a Layer, stored as a structure of arrays: the
+/// values of each Register of the Neurons are kept in a contiguous Vec of f64, with one
+/// cell per Neuron, and the synapse Registers of all the Neurons are kept one after the
+/// other in a single Vec (see SynapseRows), the kernel of a convolutional layer being
+/// stored once for all its Neurons. Updating the layer then scans contiguous memory,
+/// instead of following the Registers of each Neuron, each one carrying its Damage.
+///
+/// Damages are kept apart, in a sparse side table indexed by Neuron and Register, which
+/// holds the Damages the Registers had when the layer was packed. The Faults passed to
+/// step are looked up in the same way.
+///
+/// The Neurons affected by a Damage, as well as those using a model which is not a
+/// built-in one or a NoiseSource, are unpacked into Neuron structs for the time step and
+/// updated by the same code as Layer; the other Neurons perform the operations of the
+/// built-in models (see NeuronModel) directly on the arrays, reading the same values the
+/// Registers would return. A PackedLayer therefore produces the same results as the Layer
+/// it has been built from, including the effects of the Damages. Models reporting a
+/// built-in model (see NeuronDynamics::builtin_model) are assumed to behave like it.
+/// The settings of the layer are the same as Layer, except for parallelism, which is
+/// not supported: the Neurons are always updated by the calling thread.
+#[derive(Clone)]
+pub struct PackedLayer {
+    v_th: Vec<f64>,
+    v_rest: Vec<f64>,
+    v_reset: Vec<f64>,
+    tau: Vec<f64>,
+    v_mem: Vec<f64>,
+    bias: Vec<f64>,
+    add_reg: Vec<f64>,
+    mul_reg: Vec<f64>,
+    cmp_reg: Vec<f64>,
+    div_reg: Vec<f64>,
+    last_received_pulse_step: Vec<usize>,
+    weights: SynapseRows,
+    internal_weights: SynapseRows,
+    delays: SynapseRows,
+    synapse_vecs: Vec<[Arc<Vec<Register>>; 3]>, // synapse Vecs of each Neuron, packed in the rows
+    synapse_sources: Vec<Option<Arc<Vec<usize>>>>, // sources of the synapses of sparsely connected Neurons
+    receptive_fields: Vec<Option<ReceptiveField>>, // positions of the Neurons of a convolutional layer
+    kernel: Option<Arc<Vec<Register>>>, // weights shared by the Neurons, if convolutional
+    delayed_pulses: Vec<Vec<DelayedPulse>>,
+    homeostasis_states: Vec<HomeostasisState>,
+    damages: HashMap<(usize, NeuronRegister), Damage>, // Damages of the Registers, by Neuron
+    reset_mode: ResetMode,
+    inhibition: LateralInhibition,
+    homeostasis: Option<Homeostasis>,
+    emitted_pulse_sources: Vec<usize>, // Neurons which fired during the last update
+}
+
+/// SynapseRows keeps the values of a Vec of synapse Registers for each Neuron of a layer
+/// (e.g. their weights) in a single Vec: the values of Neuron 'i' are in
+/// values[bounds[i].0..bounds[i].1]. Consecutive Neurons sharing the same Vec (e.g. the
+/// kernel of a convolutional layer) share the same row as well.
+#[derive(Clone)]
+struct SynapseRows {
+    values: Vec<f64>,
+    bounds: Vec<(usize, usize)>,
+}
+
+impl SynapseRows {
+    /// Pack the Vecs of synapse Registers of the Neurons, in order, moving their Damages to
+    /// 'damages' ('register' identifies the synapse Register of a Neuron given its index)
+    fn pack(
+        neurons: &[Neuron],
+        synapses: fn(&Neuron) -> &[Register],
+        register: fn(usize) -> NeuronRegister,
+        damages: &mut HashMap<(usize, NeuronRegister), Damage>,
+    ) -> Self {
+        let mut values = Vec::new();
+        let mut bounds: Vec<(usize, usize)> = Vec::with_capacity(neurons.len());
+        let mut row_damages = Vec::new();
+        for (i, neuron) in neurons.iter().enumerate() {
+            let shares_previous_row =
+                i > 0 && std::ptr::eq(synapses(neuron), synapses(&neurons[i - 1]));
+            if shares_previous_row {
+                bounds.push(bounds[i - 1]);
+            } else {
+                let start = values.len();
+                row_damages.clear();
+                for (index, synapse) in synapses(neuron).iter().enumerate() {
+                    values.push(synapse.stored_value());
+                    if !matches!(synapse.damage(), Damage::Working) {
+                        row_damages.push((register(index), synapse.damage()));
+                    }
+                }
+                bounds.push((start, values.len()));
+            }
+            // the Damages of a shared row are those of each Neuron sharing it
+            for (register, damage) in row_damages.iter() {
+                damages.insert((i, *register), *damage);
+            }
+        }
+        SynapseRows { values, bounds }
+    }
+
+    /// Get the values of the synapse Registers of Neuron 'i'
+    fn row(&self, i: usize) -> &[f64] {
+        let (start, end) = self.bounds[i];
+        &self.values[start..end]
+    }
+
+    /// Build the Vec of synapse Registers of Neuron 'i', with no damages
+    fn unpack(&self, i: usize) -> Arc<Vec<Register>> {
+        Arc::new(
+            self.row(i)
+                .iter()
+                .map(|value| Register::new(*value))
+                .collect(),
+        )
+    }
+}
+
+impl PackedLayer {
+    /// Get the number of Neurons in the layer
+    pub fn len(&self) -> usize {
+        self.v_mem.len()
+    }
+
+    /// Check whether the layer contains no Neurons
+    pub fn is_empty(&self) -> bool {
+        self.v_mem.is_empty()
+    }
+
+    /// Get the indexes of the Neurons which fired during the last update of the layer
+    pub fn emitted_pulse_sources(&self) -> &[usize] {
+        &self.emitted_pulse_sources
+    }
+
+    /// Get a copy of the specified Register of a Neuron, with the Damage it had when the
+    /// layer was packed, if the Register exists
+    pub fn register(&self, neuron: usize, register: NeuronRegister) -> Option<Register> {
+        if neuron >= self.len() {
+            return None;
+        }
+        let value = match register {
+            NeuronRegister::Weight(index) => self.weights.row(neuron).get(index).copied(),
+            NeuronRegister::InternalWeight(index) => {
+                self.internal_weights.row(neuron).get(index).copied()
+            }
+            NeuronRegister::Delay(index) => self.delays.row(neuron).get(index).copied(),
+            NeuronRegister::Threshold => Some(self.v_th[neuron]),
+            NeuronRegister::MembranePotential => Some(self.v_mem[neuron]),
+            NeuronRegister::ResetPotential => Some(self.v_reset[neuron]),
+            NeuronRegister::PotentialAtRest => Some(self.v_rest[neuron]),
+            NeuronRegister::Bias => Some(self.bias[neuron]),
+            NeuronRegister::Comparator => Some(self.cmp_reg[neuron]),
+            NeuronRegister::Adder => Some(self.add_reg[neuron]),
+            NeuronRegister::Multiplier => Some(self.mul_reg[neuron]),
+            NeuronRegister::Divider => Some(self.div_reg[neuron]),
+        }?;
+
+        let mut packed_register = Register::new(value);
+        if let Some(damage) = self.damages.get(&(neuron, register)) {
+            packed_register.apply_damage(*damage);
+        }
+        Some(packed_register)
+    }
+
+    /// Get a copy of the specified Register of a Neuron as it is while the layer is
+    /// simulated, i.e. with the Faults affecting the layer ('faults', see
+    /// FaultOverlay::of_layer) applied as overlay::arm does: a Fault on a weight of the
+    /// kernel affects the same weight of every Neuron sharing it.
+    pub(crate) fn armed_register(
+        &self,
+        neuron: usize,
+        register: NeuronRegister,
+        faults: &[Fault],
+    ) -> Option<Register> {
+        let mut armed_register = self.register(neuron, register)?;
+        for fault in faults {
+            if fault.address.register == register
+                && (fault.address.neuron == neuron
+                    || (self.is_kernel_fault(fault) && self.shares_kernel(neuron)))
+            {
+                armed_register.apply_damage(fault.damage);
+            }
+        }
+        Some(armed_register)
+    }
+
+    /// Write the state of the Neurons of the PackedLayer back to the Layer it has been
+    /// built from, so that the Layer continues the simulation from where the PackedLayer
+    /// stopped. Damages of the Layer are left unchanged.
+    pub fn store_state(&self, layer: &mut Layer) {
+        for (i, neuron) in layer.neurons.iter_mut().enumerate() {
+            neuron.v_th.write_value(self.v_th[i]);
+            neuron.v_rest.write_value(self.v_rest[i]);
+            neuron.v_reset.write_value(self.v_reset[i]);
+            neuron.tau.write_value(self.tau[i]);
+            neuron.v_mem.write_value(self.v_mem[i]);
+            neuron.bias.write_value(self.bias[i]);
+            neuron.add_reg.write_value(self.add_reg[i]);
+            neuron.mul_reg.write_value(self.mul_reg[i]);
+            neuron.cmp_reg.write_value(self.cmp_reg[i]);
+            neuron.div_reg.write_value(self.div_reg[i]);
+            neuron.last_received_pulse_step = self.last_received_pulse_step[i];
+            neuron.delayed_pulses.clone_from(&self.delayed_pulses[i]);
+            neuron.homeostasis_state = self.homeostasis_states[i];
+        }
+        layer
+            .emitted_pulse_sources
+            .clone_from(&self.emitted_pulse_sources);
+    }
+
+    /// Bring the PackedLayer to the state of 'layer', so that it can be reused for another
+    /// simulation of the same Layer. The synapse Registers are packed again only if the
+    /// Neurons of the Layer do not share the Vecs the PackedLayer has been built from: since
+    /// the PackedLayer keeps a reference to them, they are copied whenever they are modified
+    /// (see Neuron::register_mut).
+    pub fn load(&mut self, layer: &Layer) {
+        if self.has_synapses_of(layer) {
+            self.load_neurons(layer);
+        } else {
+            *self = PackedLayer::from(layer);
+        }
+    }
+
+    /// Check whether the synapse rows have been packed from the synapse Vecs the Neurons of
+    /// 'layer' still share
+    fn has_synapses_of(&self, layer: &Layer) -> bool {
+        self.synapse_vecs.len() == layer.len()
+            && self.kernel.as_ref().map(Arc::as_ptr)
+                == layer
+                    .kernel
+                    .as_ref()
+                    .map(|kernel| Arc::as_ptr(&kernel.weights))
+            && self
+                .synapse_vecs
+                .iter()
+                .zip(&self.synapse_sources)
+                .zip(&layer.neurons)
+                .all(|(([weights, internal_weights, delays], sources), neuron)| {
+                    Arc::ptr_eq(weights, &neuron.weights)
+                        && Arc::ptr_eq(internal_weights, &neuron.internal_weights)
+                        && Arc::ptr_eq(delays, &neuron.delays)
+                        && sources.as_ref().map(Arc::as_ptr)
+                            == neuron.synapse_sources.as_ref().map(Arc::as_ptr)
+                })
+            && self
+                .receptive_fields
+                .iter()
+                .zip(&layer.neurons)
+                .all(|(receptive_field, neuron)| *receptive_field == neuron.receptive_field)
+    }
+
+    /// Copy the Registers of the Neurons of 'layer' which are not synapse Registers, together
+    /// with their Damages, the state of the Neurons and the settings of the layer
+    fn load_neurons(&mut self, layer: &Layer) {
+        let neurons = &layer.neurons;
+        let load = |values: &mut Vec<f64>, register: fn(&Neuron) -> &Register| {
+            values.clear();
+            values.extend(neurons.iter().map(|neuron| register(neuron).stored_value()));
+        };
+        load(&mut self.v_th, |neuron| &neuron.v_th);
+        load(&mut self.v_rest, |neuron| &neuron.v_rest);
+        load(&mut self.v_reset, |neuron| &neuron.v_reset);
+        load(&mut self.tau, |neuron| &neuron.tau);
+        load(&mut self.v_mem, |neuron| &neuron.v_mem);
+        load(&mut self.bias, |neuron| &neuron.bias);
+        load(&mut self.add_reg, |neuron| &neuron.add_reg);
+        load(&mut self.mul_reg, |neuron| &neuron.mul_reg);
+        load(&mut self.cmp_reg, |neuron| &neuron.cmp_reg);
+        load(&mut self.div_reg, |neuron| &neuron.div_reg);
+        self.last_received_pulse_step = neurons
+            .iter()
+            .map(|neuron| neuron.last_received_pulse_step)
+            .collect();
+        self.delayed_pulses = neurons
+            .iter()
+            .map(|neuron| neuron.delayed_pulses.clone())
+            .collect();
+        self.homeostasis_states = neurons
+            .iter()
+            .map(|neuron| neuron.homeostasis_state)
+            .collect();
+
+        // Damages of the synapse Registers are kept, since the synapse Vecs are unchanged
+        self.damages.retain(|(_, register), _| {
+            matches!(
+                register,
+                NeuronRegister::Weight(_)
+                    | NeuronRegister::InternalWeight(_)
+                    | NeuronRegister::Delay(_)
+            )
+        });
+        for (i, neuron) in neurons.iter().enumerate() {
+            let registers = [
+                (NeuronRegister::Threshold, &neuron.v_th),
+                (NeuronRegister::MembranePotential, &neuron.v_mem),
+                (NeuronRegister::ResetPotential, &neuron.v_reset),
+                (NeuronRegister::PotentialAtRest, &neuron.v_rest),
+                (NeuronRegister::Bias, &neuron.bias),
+                (NeuronRegister::Comparator, &neuron.cmp_reg),
+                (NeuronRegister::Adder, &neuron.add_reg),
+                (NeuronRegister::Multiplier, &neuron.mul_reg),
+                (NeuronRegister::Divider, &neuron.div_reg),
+            ];
+            for (register, value) in registers {
+                if !matches!(value.damage(), Damage::Working) {
+                    self.damages.insert((i, register), value.damage());
+                }
+            }
+        }
+
+        self.reset_mode = layer.reset_mode;
+        self.inhibition = layer.inhibition;
+        self.homeostasis = layer.homeostasis;
+        self.emitted_pulse_sources
+            .clone_from(&layer.emitted_pulse_sources);
+    }
+
+    /// Simulate the layer for a time step, in the same way as Layer::step
+    pub fn step(
+        &mut self,
+        pulse_sources: &[usize],
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        noise: Option<&mut NoiseSource>,
+        faults: &[Fault],
+    ) -> Vec<usize> {
+        // built-in model followed by each Neuron, if any
+        let builtin_models = neuron_models
+            .iter()
+            .map(|model| model.builtin_model())
+            .collect::<Vec<Option<NeuronModel>>>();
+
+        let mut unpacked = self.unpack_irregular(&builtin_models, noise.is_some(), faults);
+        let emitted_pulse_sources = self.update(
+            &mut unpacked,
+            pulse_sources,
+            time_step,
+            time_step_duration_ms,
+            neuron_models,
+            &builtin_models,
+            noise,
+        );
+        for (i, neuron) in unpacked {
+            self.pack(i, &neuron);
+        }
+
+        emitted_pulse_sources
+    }
+
+    /// Unpack the Neurons which cannot be updated on the arrays: those affected by a Damage
+    /// (either from the side table or from 'faults'), those which do not follow a built-in
+    /// model and, if the layer is affected by noise, all of them.
+    /// A Fault on a weight of the kernel of a convolutional layer affects all the Neurons
+    /// sharing it (see overlay::arm), which are unpacked sharing the same damaged kernel.
+    fn unpack_irregular(
+        &self,
+        builtin_models: &[Option<NeuronModel>],
+        noisy: bool,
+        faults: &[Fault],
+    ) -> BTreeMap<usize, Neuron> {
+        let (kernel_faults, faults) = faults
+            .iter()
+            .copied()
+            .partition::<Vec<Fault>, _>(|fault| self.is_kernel_fault(fault));
+
+        let mut damaged = self
+            .damages
+            .keys()
+            .map(|(neuron, _)| *neuron)
+            .chain(faults.iter().map(|fault| fault.address.neuron))
+            .collect::<BTreeSet<usize>>();
+
+        let kernel = self.kernel.as_ref().map(|kernel| {
+            if kernel_faults.is_empty() {
+                return kernel.clone();
+            }
+            damaged.extend((0..self.len()).filter(|i| self.shares_kernel(*i)));
+            let mut damaged_kernel = (**kernel).clone();
+            for fault in kernel_faults.iter() {
+                if let NeuronRegister::Weight(index) = fault.address.register {
+                    damaged_kernel[index].apply_damage(fault.damage);
+                }
+            }
+            Arc::new(damaged_kernel)
+        });
+
+        (0..self.len())
+            .filter(|i| noisy || builtin_models[*i].is_none() || damaged.contains(i))
+            .map(|i| (i, self.unpack(i, &faults, kernel.as_ref())))
+            .collect()
+    }
+
+    /// Check whether Neuron 'i' uses the kernel of the layer as its weights
+    fn shares_kernel(&self, i: usize) -> bool {
+        self.kernel
+            .as_ref()
+            .is_some_and(|kernel| Arc::ptr_eq(kernel, &self.synapse_vecs[i][0]))
+    }
+
+    /// Check whether a Fault affects a weight of the kernel of the layer
+    fn is_kernel_fault(&self, fault: &Fault) -> bool {
+        matches!(fault.address.register, NeuronRegister::Weight(_))
+            && fault.address.neuron < self.len()
+            && self.shares_kernel(fault.address.neuron)
+    }
+
+    /// Build Neuron 'i' as a Neuron struct, applying to its Registers the Damages of the
+    /// side table and then 'faults'. If the Neuron shares the kernel of the layer, its
+    /// weights are 'kernel', which already carries the Damages of its Registers.
+    fn unpack(&self, i: usize, faults: &[Fault], kernel: Option<&Arc<Vec<Register>>>) -> Neuron {
+        let kernel = kernel.filter(|_| self.shares_kernel(i));
+        let mut neuron = Neuron {
+            v_th: Register::new(self.v_th[i]),
+            v_rest: Register::new(self.v_rest[i]),
+            v_reset: Register::new(self.v_reset[i]),
+            tau: Register::new(self.tau[i]),
+            v_mem: Register::new(self.v_mem[i]),
+            last_received_pulse_step: self.last_received_pulse_step[i],
+            weights: match kernel {
+                Some(kernel) => kernel.clone(),
+                None => self.weights.unpack(i),
+            },
+            synapse_sources: self.synapse_sources[i].clone(),
+            receptive_field: self.receptive_fields[i],
+            internal_weights: self.internal_weights.unpack(i),
+            bias: Register::new(self.bias[i]),
+            delays: self.delays.unpack(i),
+            delayed_pulses: self.delayed_pulses[i].clone(),
+            add_reg: Register::new(self.add_reg[i]),
+            mul_reg: Register::new(self.mul_reg[i]),
+            cmp_reg: Register::new(self.cmp_reg[i]),
+            div_reg: Register::new(self.div_reg[i]),
+            model: None,
+            homeostasis_state: self.homeostasis_states[i],
+        };
+
+        // Faults are applied last, replacing the Damages of the side table (see overlay::arm)
+        let damages = self
+            .damages
+            .iter()
+            .filter(|((neuron, _), _)| *neuron == i)
+            .map(|((_, register), damage)| (*register, *damage))
+            .chain(
+                faults
+                    .iter()
+                    .filter(|fault| fault.address.neuron == i)
+                    .map(|fault| (fault.address.register, fault.damage)),
+            )
+            .filter(|(register, _)| {
+                kernel.is_none() || !matches!(register, NeuronRegister::Weight(_))
+            });
+        for (register, damage) in damages {
+            if let Some(register) = neuron.register_mut(register) {
+                register.apply_damage(damage);
+            }
+        }
+
+        neuron
+    }
+
+    /// Store the state of an unpacked Neuron back into the arrays
+    fn pack(&mut self, i: usize, neuron: &Neuron) {
+        self.v_th[i] = neuron.v_th.stored_value();
+        self.v_rest[i] = neuron.v_rest.stored_value();
+        self.v_reset[i] = neuron.v_reset.stored_value();
+        self.tau[i] = neuron.tau.stored_value();
+        self.v_mem[i] = neuron.v_mem.stored_value();
+        self.bias[i] = neuron.bias.stored_value();
+        self.add_reg[i] = neuron.add_reg.stored_value();
+        self.mul_reg[i] = neuron.mul_reg.stored_value();
+        self.cmp_reg[i] = neuron.cmp_reg.stored_value();
+        self.div_reg[i] = neuron.div_reg.stored_value();
+        self.last_received_pulse_step[i] = neuron.last_received_pulse_step;
+        self.delayed_pulses[i].clone_from(&neuron.delayed_pulses);
+        self.homeostasis_states[i] = neuron.homeostasis_state;
+    }
+
+    /// Simulate the layer for a time step (see Layer::update): the Neurons in 'unpacked'
+    /// are updated as Neuron structs, the other ones on the arrays
+    #[allow(clippy::too_many_arguments)]
+    fn update(
+        &mut self,
+        unpacked: &mut BTreeMap<usize, Neuron>,
+        pulse_sources: &[usize],
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        builtin_models: &[Option<NeuronModel>],
+        mut noise: Option<&mut NoiseSource>,
+    ) -> Vec<usize> {
+        // apply inhibitive contribution due to pulses generated by nodes of the current layer
+        // during previous time step (except for time step 0)
+        if time_step > 0 {
+            self.inhibit(
+                unpacked,
+                time_step,
+                time_step_duration_ms,
+                neuron_models,
+                builtin_models,
+                noise.as_deref_mut(),
+            );
+        }
+        self.emitted_pulse_sources.clear();
+
+        // Pulses each Neuron has to process during the current time step
+        let due_pulse_sources = (0..self.len())
+            .map(|i| match unpacked.get_mut(&i) {
+                Some(neuron) => Cow::Owned(neuron.receive_pulses(pulse_sources, time_step)),
+                None => self.receive_pulses(i, pulse_sources, time_step),
+            })
+            .collect::<Vec<Cow<[usize]>>>();
+
+        // Neurons are updated ONLY if at least one of them receives some input
+        if due_pulse_sources
+            .iter()
+            .enumerate()
+            .all(|(i, sources)| !self.receives_input(unpacked, i, sources, time_step))
+        {
+            self.regulate_thresholds(unpacked, time_step);
+            return Vec::new();
+        }
+
+        let k_winners = match self.inhibition {
+            LateralInhibition::KWinnerTakeAll { k } => Some(k),
+            LateralInhibition::Dense | LateralInhibition::Uniform { .. } => None,
+        };
+
+        // integrate pulses in all neurons which receive some input, collecting those which
+        // would fire together with their membrane potential
+        let mut candidates = Vec::new();
+        for (i, due_pulse_sources) in due_pulse_sources.iter().enumerate() {
+            if !self.receives_input(unpacked, i, due_pulse_sources, time_step) {
+                continue;
+            }
+            let model = neuron_models[i].as_ref();
+            let fires = match unpacked.get_mut(&i) {
+                Some(neuron) => {
+                    let due_pulse_sources = due_pulse_sources.to_vec();
+                    match k_winners {
+                        Some(_) => {
+                            neuron.integrate_pulses(
+                                &due_pulse_sources,
+                                time_step,
+                                time_step_duration_ms,
+                                model,
+                                noise.as_deref_mut(),
+                            );
+                            neuron.fires(time_step, model, noise.as_deref_mut())
+                        }
+                        None => neuron.feed_pulses(
+                            &due_pulse_sources,
+                            time_step,
+                            time_step_duration_ms,
+                            model,
+                            noise.as_deref_mut(),
+                            self.reset_mode,
+                        ),
+                    }
+                }
+                None => {
+                    let builtin_model = builtin_models[i].unwrap();
+                    let contribution = self.pulses_contribution(i, due_pulse_sources);
+                    self.update_membrane_potential(
+                        i,
+                        contribution,
+                        time_step,
+                        time_step_duration_ms,
+                        builtin_model,
+                    );
+                    self.last_received_pulse_step[i] = time_step;
+                    let fires = self.fires(i);
+                    if fires && k_winners.is_none() {
+                        self.reset(i);
+                    }
+                    fires
+                }
+            };
+            if fires {
+                let v_mem = match unpacked.get(&i) {
+                    Some(neuron) => neuron.v_mem.read_value(Some(time_step)).unwrap(),
+                    None => self.v_mem[i],
+                };
+                candidates.push((i, v_mem));
+            }
+        }
+
+        // with k-winner-take-all inhibition, only the k candidates with the highest membrane
+        // potential fire (see Layer::feed_k_winners) and they are reset now
+        if let Some(k) = k_winners {
+            candidates.sort_by(|(_, v1), (_, v2)| v2.total_cmp(v1));
+            candidates.truncate(k);
+            candidates.sort_by_key(|(i, _)| *i);
+            for (i, _) in &candidates {
+                match unpacked.get_mut(i) {
+                    Some(neuron) => neuron_models[*i].reset(neuron, self.reset_mode, time_step),
+                    None => self.reset(*i),
+                }
+            }
+        }
+        self.emitted_pulse_sources = candidates.into_iter().map(|(i, _)| i).collect();
+
+        self.regulate_thresholds(unpacked, time_step);
+
+        self.emitted_pulse_sources.clone()
+    }
+
+    /// apply the inhibitive contribution due to the pulses emitted by the layer during
+    /// the previous time step (see Layer::inhibit)
+    fn inhibit(
+        &mut self,
+        unpacked: &mut BTreeMap<usize, Neuron>,
+        time_step: usize,
+        time_step_duration_ms: f64,
+        neuron_models: &[Arc<dyn NeuronDynamics>],
+        builtin_models: &[Option<NeuronModel>],
+        mut noise: Option<&mut NoiseSource>,
+    ) {
+        // competition is resolved while firing, there is no inhibition afterwards
+        if let LateralInhibition::KWinnerTakeAll { .. } = self.inhibition {
+            return;
+        }
+
+        let emitted_pulse_sources = std::mem::take(&mut self.emitted_pulse_sources);
+        for i in 0..self.len() {
+            let model = neuron_models[i].as_ref();
+            match (unpacked.get_mut(&i), self.inhibition) {
+                (Some(neuron), LateralInhibition::Uniform { strength }) => {
+                    // a Neuron is not inhibited by its own pulses
+                    let other_pulse_sources = emitted_pulse_sources
+                        .iter()
+                        .copied()
+                        .filter(|source| *source != i)
+                        .collect::<Vec<usize>>();
+                    neuron.inhibite_uniformly_after_pulses_emission(
+                        &other_pulse_sources,
+                        strength,
+                        time_step,
+                        time_step_duration_ms,
+                        model,
+                        noise.as_deref_mut(),
+                    );
+                }
+                (Some(neuron), _) => neuron.inhibite_after_pulses_emission(
+                    &emitted_pulse_sources,
+                    time_step,
+                    time_step_duration_ms,
+                    model,
+                    noise.as_deref_mut(),
+                ),
+                (None, inhibition) => {
+                    let contribution = match inhibition {
+                        LateralInhibition::Uniform { strength } => {
+                            let mut contribution = 0.0;
+                            for _ in emitted_pulse_sources.iter().filter(|source| **source != i) {
+                                contribution -= strength;
+                            }
+                            contribution
+                        }
+                        _ => {
+                            let internal_weights = self.internal_weights.row(i);
+                            let mut contribution = 0.0;
+                            for source_index in &emitted_pulse_sources {
+                                contribution += internal_weights[*source_index];
+                            }
+                            contribution
+                        }
+                    };
+                    self.update_membrane_potential(
+                        i,
+                        contribution,
+                        time_step,
+                        time_step_duration_ms,
+                        builtin_models[i].unwrap(),
+                    );
+                    self.last_received_pulse_step[i] = time_step;
+                }
+            }
+        }
+        self.emitted_pulse_sources = emitted_pulse_sources;
+    }
+
+    /// Buffer the Pulses received by Neuron 'i' on delayed synapses and return the sources
+    /// of the Pulses to be delivered at the current time step (see Neuron::receive_pulses)
+    fn receive_pulses<'a>(
+        &mut self,
+        i: usize,
+        pulse_sources: &'a [usize],
+        time_step: usize,
+    ) -> Cow<'a, [usize]> {
+        // a sparsely connected Neuron ignores the pulses of Neurons it has no synapse from,
+        // a Neuron of a convolutional layer those from outside its window
+        let connected_pulse_sources =
+            if self.synapse_sources[i].is_some() || self.receptive_fields[i].is_some() {
+                Cow::Owned(
+                    pulse_sources
+                        .iter()
+                        .copied()
+                        .filter(|source_index| self.find_synapse(i, *source_index).is_some())
+                        .collect(),
+                )
+            } else {
+                Cow::Borrowed(pulse_sources)
+            };
+
+        let delays = self.delays.row(i);
+        // no delay lines: all pulses are delivered immediately
+        if delays.is_empty() && self.delayed_pulses[i].is_empty() {
+            return connected_pulse_sources;
+        }
+
+        let mut due_pulse_sources = Vec::new();
+        for source_index in connected_pulse_sources.iter().copied() {
+            let delay = delays
+                .get(self.synapse_index(i, source_index))
+                .map_or(0, |delay| *delay as usize);
+            if delay == 0 {
+                due_pulse_sources.push(source_index);
+            } else {
+                self.delayed_pulses[i].push(DelayedPulse {
+                    source_index,
+                    delivery_step: time_step.saturating_add(delay),
+                });
+            }
+        }
+
+        // collecting buffered pulses which must be delivered at the current time step
+        self.delayed_pulses[i].retain(|pulse| {
+            if pulse.delivery_step <= time_step {
+                due_pulse_sources.push(pulse.source_index);
+                return false;
+            }
+            true
+        });
+
+        Cow::Owned(due_pulse_sources)
+    }
+
+    /// Check whether Neuron 'i' has to integrate its input at the current time step, given
+    /// the sources of the Pulses delivered to it (see Neuron::receives_input)
+    fn receives_input(
+        &self,
+        unpacked: &BTreeMap<usize, Neuron>,
+        i: usize,
+        due_pulse_sources: &[usize],
+        time_step: usize,
+    ) -> bool {
+        match unpacked.get(&i) {
+            Some(neuron) => neuron.receives_input(due_pulse_sources, time_step),
+            None => !due_pulse_sources.is_empty() || self.bias[i] != 0.0,
+        }
+    }
+
+    /// compute the contribution to the membrane potential of Neuron 'i' due to the received
+    /// pulses, including the bias (see Neuron::update_membrane_potential)
+    fn pulses_contribution(&self, i: usize, pulse_sources: &[usize]) -> f64 {
+        let weights = self.weights.row(i);
+        let mut contribution = 0.0;
+        for source_index in pulse_sources {
+            contribution += weights[self.synapse_index(i, *source_index)];
+        }
+        contribution + self.bias[i]
+    }
+
+    /// Get the index of the synapse of Neuron 'i' going from the Neuron 'source_index' of
+    /// the previous layer, which must exist (see Neuron::synapse_index)
+    fn synapse_index(&self, i: usize, source_index: usize) -> usize {
+        self.find_synapse(i, source_index)
+            .expect("the Neuron has no synapse from the source of the pulse")
+    }
+
+    /// Get the index of the synapse of Neuron 'i' going from the Neuron 'source_index' of
+    /// the previous layer, if it exists (see Neuron::synapse_index)
+    fn find_synapse(&self, i: usize, source_index: usize) -> Option<usize> {
+        if let Some(receptive_field) = &self.receptive_fields[i] {
+            return receptive_field
+                .convolution
+                .kernel_index(receptive_field.output_index, source_index);
+        }
+        match &self.synapse_sources[i] {
+            Some(synapse_sources) => synapse_sources.binary_search(&source_index).ok(),
+            None => Some(source_index),
+        }
+    }
+
+    /// Update the membrane potential of Neuron 'i' performing the same operations as the
+    /// built-in model (see LeakyIntegrateAndFire and IntegrateAndFire)
+    fn update_membrane_potential(
+        &mut self,
+        i: usize,
+        pulses_contribution: f64,
+        time_step: usize,
+        time_step_duration_ms: f64,
+        model: NeuronModel,
+    ) {
+        match model {
+            NeuronModel::LeakyIntegrateAndFire => {
+                let pulses_contrib = self.v_mem[i] + pulses_contribution;
+                let vm_vr = self.v_mem[i] - self.v_rest[i];
+                let diff_steps = self.last_received_pulse_step[i] as f64 - time_step as f64;
+                self.mul_reg[i] = diff_steps * time_step_duration_ms;
+                self.div_reg[i] = self.mul_reg[i] / self.tau[i];
+                self.mul_reg[i] = self.div_reg[i].exp() * vm_vr;
+                self.add_reg[i] = self.mul_reg[i] + pulses_contrib;
+            }
+            NeuronModel::IntegrateAndFire => {
+                self.add_reg[i] = self.v_mem[i] + pulses_contribution;
+            }
+        }
+        self.v_mem[i] = self.add_reg[i];
+    }
+
+    /// Check whether Neuron 'i' fires, comparing v_mem to the threshold (see
+    /// NeuronDynamics::fires)
+    fn fires(&mut self, i: usize) -> bool {
+        self.cmp_reg[i] = self.v_mem[i] - self.v_th[i];
+        self.cmp_reg[i] >= 0.0
+    }
+
+    /// Reset the membrane potential of Neuron 'i' after it fired (see NeuronDynamics::reset)
+    fn reset(&mut self, i: usize) {
+        match self.reset_mode {
+            ResetMode::ToResetPotential => self.v_mem[i] = self.v_reset[i],
+            ResetMode::BySubtraction => {
+                self.add_reg[i] = self.v_mem[i] - self.v_th[i];
+                self.v_mem[i] = self.add_reg[i];
+            }
+            ResetMode::NoReset => {}
+        }
+    }
+
+    /// let each Neuron regulate its threshold according to whether it fired during the
+    /// current time step, if the layer uses homeostasis (see Neuron::regulate_threshold)
+    fn regulate_thresholds(&mut self, unpacked: &mut BTreeMap<usize, Neuron>, time_step: usize) {
+        let Some(homeostasis) = self.homeostasis else {
+            return;
+        };
+
+        for i in 0..self.len() {
+            let fired = self.emitted_pulse_sources.binary_search(&i).is_ok();
+            if let Some(neuron) = unpacked.get_mut(&i) {
+                neuron.regulate_threshold(fired, &homeostasis, time_step);
+                continue;
+            }
+
+            let state = &mut self.homeostasis_states[i];
+            state.elapsed_steps += 1;
+            if fired {
+                state.spike_count += 1;
+            }
+            if state.elapsed_steps >= homeostasis.window {
+                let firing_rate = state.spike_count as f64 / state.elapsed_steps as f64;
+                let threshold_change =
+                    homeostasis.learning_rate * (firing_rate - homeostasis.target_rate);
+                self.add_reg[i] = self.v_th[i] + threshold_change;
+                self.v_th[i] = self.add_reg[i];
+                *state = HomeostasisState::default();
+            }
+        }
+    }
+}
+
+impl From<&Layer> for PackedLayer {
+    /// Pack the Neurons of a Layer, moving the Damages of their Registers to the side table
+    fn from(layer: &Layer) -> Self {
+        let neurons = &layer.neurons;
+
+        let mut damages = HashMap::new();
+        let weights = SynapseRows::pack(
+            neurons,
+            |neuron| &neuron.weights,
+            NeuronRegister::Weight,
+            &mut damages,
+        );
+        let internal_weights = SynapseRows::pack(
+            neurons,
+            |neuron| &neuron.internal_weights,
+            NeuronRegister::InternalWeight,
+            &mut damages,
+        );
+        let delays = SynapseRows::pack(
+            neurons,
+            |neuron| &neuron.delays,
+            NeuronRegister::Delay,
+            &mut damages,
+        );
+
+        let mut packed_layer = PackedLayer {
+            v_th: Vec::new(),
+            v_rest: Vec::new(),
+            v_reset: Vec::new(),
+            tau: Vec::new(),
+            v_mem: Vec::new(),
+            bias: Vec::new(),
+            add_reg: Vec::new(),
+            mul_reg: Vec::new(),
+            cmp_reg: Vec::new(),
+            div_reg: Vec::new(),
+            last_received_pulse_step: Vec::new(),
+            weights,
+            internal_weights,
+            delays,
+            synapse_vecs: neurons
+                .iter()
+                .map(|neuron| {
+                    [
+                        neuron.weights.clone(),
+                        neuron.internal_weights.clone(),
+                        neuron.delays.clone(),
+                    ]
+                })
+                .collect(),
+            synapse_sources: neurons
+                .iter()
+                .map(|neuron| neuron.synapse_sources.clone())
+                .collect(),
+            receptive_fields: neurons
+                .iter()
+                .map(|neuron| neuron.receptive_field)
+                .collect(),
+            kernel: layer.kernel.as_ref().map(|kernel| kernel.weights.clone()),
+            delayed_pulses: Vec::new(),
+            homeostasis_states: Vec::new(),
+            damages,
+            reset_mode: layer.reset_mode,
+            inhibition: layer.inhibition,
+            homeostasis: layer.homeostasis,
+            emitted_pulse_sources: Vec::new(),
+        };
+        packed_layer.load_neurons(layer);
+        packed_layer
+    }
+}
@@ -0,0 +1,267 @@
+use crate::network::layer::Layer;
+use crate::network::neuron::NeuronRegister;
+use crate::network::overlay::{self, FaultOverlay};
+use crate::network::packed::PackedLayer;
+use crate::network::{Fault, Network, RegisterAddress};
+use crate::register::Register;
+use serde::{Deserialize, Serialize};
+
+/// Probes collects the addresses of the Registers whose values must be recorded
+/// at every time step of a simulation, in order to trace how values (and damages)
+/// propagate through the network.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Probes(pub Vec<RegisterAddress>);
+
+impl Probes {
+    /// Create an empty set of probes
+    pub fn new() -> Self {
+        Probes(Vec::new())
+    }
+
+    /// Probe the specified Register of a single Neuron
+    pub fn probe(&mut self, layer: usize, neuron: usize, register: NeuronRegister) -> &mut Self {
+        self.0.push(RegisterAddress {
+            layer,
+            neuron,
+            register,
+        });
+        self
+    }
+
+    /// Probe the specified Register of all the Neurons of a layer
+    pub fn probe_layer(
+        &mut self,
+        network: &Network,
+        layer: usize,
+        register: NeuronRegister,
+    ) -> &mut Self {
+        for neuron in 0..network.layers[layer].len() {
+            self.probe(layer, neuron, register);
+        }
+        self
+    }
+
+    /// Probe the specified Register of all the Neurons of the network
+    pub fn probe_all(&mut self, network: &Network, register: NeuronRegister) -> &mut Self {
+        for layer in 0..network.layers.len() {
+            self.probe_layer(network, layer, register);
+        }
+        self
+    }
+
+    /// Get the probes belonging to a layer, together with their position in the set
+    pub(crate) fn of_layer(&self, layer: usize) -> Vec<(usize, RegisterAddress)> {
+        self.0
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, address)| address.layer == layer)
+            .collect()
+    }
+
+    /// Read the values of the probes belonging to a layer at the end of a time step,
+    /// appending them to the corresponding Vecs of 'values'. Values are read as the
+    /// hardware would read them, i.e. from the layer with the Faults affecting it
+    /// ('layer_faults') applied (see overlay::arm): a Fault on a weight of the kernel of a
+    /// convolutional layer is seen through every Neuron sharing it.
+    /// Probes referring to Registers which do not exist record NaN.
+    pub(crate) fn record(
+        layer_probes: &[(usize, RegisterAddress)],
+        layer: &mut Layer,
+        layer_faults: &[Fault],
+        time_step: usize,
+        values: &mut [Vec<f64>],
+    ) {
+        if layer_probes.is_empty() {
+            return;
+        }
+
+        let armed_faults = overlay::arm(layer, layer_faults);
+        for (values, (_, address)) in values.iter_mut().zip(layer_probes) {
+            values.push(Self::read(Self::register_of(layer, address), time_step));
+        }
+        overlay::disarm(layer, layer_faults, armed_faults);
+    }
+
+    /// Read the value of a probed Register ('register', None if it does not exist) at the
+    /// specified time step
+    fn read(register: Option<Register>, time_step: usize) -> f64 {
+        register
+            .and_then(|register| register.read_value(Some(time_step)))
+            .unwrap_or(f64::NAN)
+    }
+
+    /// Get a copy of the Register of a layer at the specified address, if it exists
+    fn register_of(layer: &Layer, address: &RegisterAddress) -> Option<Register> {
+        layer
+            .neurons
+            .get(address.neuron)
+            .and_then(|neuron| neuron.register(address.register))
+            .copied()
+    }
+}
+
+/// A Trace holds the values recorded by a set of Probes during a simulation:
+/// values[i][j] is the value of the Register probes[i] at the end of time step 'j'.
+/// Probes referring to Registers which do not exist record NaN, which is serialized to
+/// json as null.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Trace {
+    pub probes: Vec<RegisterAddress>,
+    #[serde(with = "nan_as_null")]
+    pub values: Vec<Vec<f64>>,
+}
+
+/// Serialization of the values of a Trace: json has no NaN, so NaN values are written as
+/// null and read back as NaN
+mod nan_as_null {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(values: &[Vec<f64>], serializer: S) -> Result<S::Ok, S::Error> {
+        values
+            .iter()
+            .map(|values| {
+                values
+                    .iter()
+                    .map(|value| Some(*value).filter(|value| !value.is_nan()))
+                    .collect::<Vec<Option<f64>>>()
+            })
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<f64>>, D::Error> {
+        Ok(Vec::<Vec<Option<f64>>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|values| {
+                values
+                    .into_iter()
+                    .map(|value| value.unwrap_or(f64::NAN))
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+impl Trace {
+    /// Create a Trace for the provided probes, with no recorded values
+    pub fn new(probes: &Probes) -> Self {
+        Trace {
+            probes: probes.0.clone(),
+            values: vec![Vec::new(); probes.0.len()],
+        }
+    }
+
+    /// Record the values of all the probed Registers of 'network' at the end of a time step
+    /// (see Probes::record)
+    pub(crate) fn record(&mut self, network: &mut Network, time_step: usize) {
+        for (values, address) in self.values.iter_mut().zip(&self.probes) {
+            if address.layer >= network.layers.len() {
+                values.push(f64::NAN);
+            }
+        }
+        for (layer_nr, layer) in network.layers.iter_mut().enumerate() {
+            let layer_probes = self
+                .probes
+                .iter()
+                .copied()
+                .enumerate()
+                .filter(|(_, address)| address.layer == layer_nr)
+                .collect::<Vec<_>>();
+            let mut probed_values = vec![Vec::new(); layer_probes.len()];
+            Probes::record(
+                &layer_probes,
+                layer,
+                network.faults.of_layer(layer_nr),
+                time_step,
+                &mut probed_values,
+            );
+            for ((probe_index, _), values) in layer_probes.into_iter().zip(probed_values) {
+                self.values[probe_index].extend(values);
+            }
+        }
+    }
+
+    /// Record the values of all the probed Registers of the PackedLayers of a network at the
+    /// end of a time step (see Engine::Packed)
+    pub(crate) fn record_packed(
+        &mut self,
+        layers: &[PackedLayer],
+        faults: &FaultOverlay,
+        time_step: usize,
+    ) {
+        for (values, address) in self.values.iter_mut().zip(&self.probes) {
+            let register = layers.get(address.layer).and_then(|layer| {
+                layer.armed_register(
+                    address.neuron,
+                    address.register,
+                    faults.of_layer(address.layer),
+                )
+            });
+            values.push(Probes::read(register, time_step));
+        }
+    }
+
+    /// Store the values recorded by the probes belonging to a layer (see Probes::record)
+    pub(crate) fn collect(&mut self, probes: &Probes, layer: usize, probed_values: Vec<Vec<f64>>) {
+        for ((probe_index, _), values) in probes.of_layer(layer).into_iter().zip(probed_values) {
+            self.values[probe_index] = values;
+        }
+    }
+
+    /// Record NaN at every time step for the probes referring to layers which do not exist
+    /// in a network with 'layers_number' layers, since no layer collects them (see collect)
+    pub(crate) fn fill_missing_layers(&mut self, layers_number: usize, time_steps_number: usize) {
+        for (values, address) in self.values.iter_mut().zip(&self.probes) {
+            if address.layer >= layers_number {
+                *values = vec![f64::NAN; time_steps_number];
+            }
+        }
+    }
+
+    /// Serialize the Trace to json
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Cannot serialize")
+    }
+
+    /// Deserialize a Trace from json (see to_json)
+    pub fn from_json(json: &str) -> Self {
+        serde_json::from_str(json).expect("Cannot deserialize")
+    }
+
+    /// Serialize the Trace to csv: the first column contains the time step, then there
+    /// is a column for each probe, named after the Register it refers to
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("time_step");
+        for address in &self.probes {
+            csv.push_str(&format!(
+                ",layer{}.neuron{}.{}",
+                address.layer,
+                address.neuron,
+                address.register.name()
+            ));
+        }
+        csv.push('\n');
+
+        let time_steps_number = self.values.first().map_or(0, |values| values.len());
+        for time_step in 0..time_steps_number {
+            csv.push_str(&time_step.to_string());
+            for values in &self.values {
+                csv.push_str(&format!(",{}", values[time_step]));
+            }
+            csv.push('\n');
+        }
+
+        csv
+    }
+}
+
+/// Result of a simulation performed with probes: the output matrix (see Network::run)
+/// and the Trace of the probed Registers
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TracedRun {
+    pub output: Vec<Vec<bool>>,
+    pub trace: Trace,
+}
@@ -0,0 +1,221 @@
+use crate::network::dynamics::NeuronDynamics;
+use crate::network::layer::Layer;
+use crate::network::noise::NoiseSource;
+use crate::network::topology::Topology;
+use crate::network::{Fault, Network, RegisterAddress};
+use std::sync::Arc;
+
+/// A Session allows to simulate a Network one time step at a time, when the input
+/// is not known in advance (e.g. when the network is driven by a closed-loop
+/// controller). At each time step the input pulses are pushed into the network and
+/// the output pulses for that same time step are returned.
+///
+/// All layers are simulated in the calling thread, using the same per-layer code
+/// as Network::run. The Session borrows the Network, which keeps its state once the
+/// Session is dropped. Time steps continue from those already simulated by the Network
+/// (see Network::time_step), so a Session can be reopened where a previous one stopped.
+pub struct Session<'a> {
+    network: &'a mut Network,
+    neuron_models: Vec<Vec<Arc<dyn NeuronDynamics>>>, // models resolved for each Neuron of each layer
+    noise_sources: Vec<Option<NoiseSource>>,          // noise source of each layer, if any
+    topology: Topology, // sources of the pulses received by each layer
+}
+
+/// Result of a time step simulated in a Session, including the internal state of
+/// the network at the end of the time step
+pub struct StepState {
+    pub output: Vec<bool>, // output[i] is true if output neuron 'i' fired
+    pub emitted_pulses: Vec<Vec<usize>>, // indexes of the Neurons which fired, for each layer
+    pub membrane_potentials: Vec<Vec<f64>>, // membrane potential of each Neuron, for each layer
+}
+
+/// A Checkpoint is a snapshot of the state of a Network simulated in a Session, taken at
+/// the beginning of a time step: once it is restored, the simulation continues exactly as
+/// it did after the snapshot was taken
+#[derive(Clone)]
+pub struct Checkpoint {
+    layers: Vec<Layer>, // layers of the network, with the state of their Neurons
+    noise_sources: Vec<Option<NoiseSource>>, // state of the noise source of each layer
+    time_step: usize,   // next time step to be simulated
+}
+
+impl Checkpoint {
+    /// Get the time step the Checkpoint has been taken at
+    pub fn time_step(&self) -> usize {
+        self.time_step
+    }
+}
+
+impl<'a> Session<'a> {
+    /// Open a Session on the provided Network
+    pub fn new(network: &'a mut Network) -> Self {
+        let neuron_models = network
+            .layers
+            .iter()
+            .map(|layer| layer.resolve_models(&network.model))
+            .collect();
+        let noise_sources = (0..network.layers.len())
+            .map(|layer_nr| network.noise.map(|noise| NoiseSource::new(noise, layer_nr)))
+            .collect();
+
+        Session {
+            topology: Topology::of(network),
+            network,
+            neuron_models,
+            noise_sources,
+        }
+    }
+
+    /// Get the time step which will be simulated by the next call to step
+    pub fn time_step(&self) -> usize {
+        self.network.time_step
+    }
+
+    /// Get the Network simulated by the Session
+    pub fn network(&self) -> &Network {
+        self.network
+    }
+
+    /// Get the Network simulated by the Session, to modify it between time steps
+    pub(crate) fn network_mut(&mut self) -> &mut Network {
+        self.network
+    }
+
+    /// Simulate a time step: 'input' must have one element for each entrance of the
+    /// network, and input[i] == true means that the entrance 'i' receives a pulse.
+    /// Returns the output of the network for the same time step: output[i] == true
+    /// means that the output neuron 'i' produced a pulse.
+    pub fn step(&mut self, input: &[bool]) -> Vec<bool> {
+        let emitted_pulses = self.step_layers(input);
+        self.output_from(&emitted_pulses)
+    }
+
+    /// Simulate a time step like 'step', also returning the internal state of the
+    /// network at the end of the time step
+    pub fn step_with_state(&mut self, input: &[bool]) -> StepState {
+        let emitted_pulses = self.step_layers(input);
+        let membrane_potentials = self
+            .network
+            .layers
+            .iter()
+            .map(|layer| {
+                layer
+                    .neurons
+                    .iter()
+                    .map(|neuron| neuron.v_mem.stored_value())
+                    .collect()
+            })
+            .collect();
+
+        StepState {
+            output: self.output_from(&emitted_pulses),
+            emitted_pulses,
+            membrane_potentials,
+        }
+    }
+
+    /// Apply a Fault to the network during the Session: it affects all the following
+    /// time steps (transient damages are referred to the time steps of the Session, see
+    /// time_step).
+    /// Returns false if the address does not correspond to any Register.
+    pub fn apply_damage(&mut self, fault: Fault) -> bool {
+        self.network.apply_damage(fault)
+    }
+
+    /// Remove any Damage from the Register at the specified address
+    pub fn remove_damage(&mut self, address: RegisterAddress) {
+        self.network.remove_damage(address);
+    }
+
+    /// Take a snapshot of the current state of the Session
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            layers: self.network.layers.clone(),
+            noise_sources: self.noise_sources.clone(),
+            time_step: self.network.time_step,
+        }
+    }
+
+    /// Bring the Session back to the state saved in a Checkpoint, taken on a Network with
+    /// the same structure. The damages applied to the network are not affected, since
+    /// they are not part of the state of the layers (see FaultOverlay).
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.network.layers.clone_from(&checkpoint.layers);
+        self.noise_sources.clone_from(&checkpoint.noise_sources);
+        self.network.time_step = checkpoint.time_step;
+    }
+
+    /// Check whether the Session is in the same state saved in a Checkpoint, i.e. whether
+    /// the simulation would continue in the same way, provided that no damage is active
+    pub fn matches(&self, checkpoint: &Checkpoint) -> bool {
+        self.network.time_step == checkpoint.time_step
+            && self.noise_sources == checkpoint.noise_sources
+            && self
+                .network
+                .layers
+                .iter()
+                .zip(&checkpoint.layers)
+                .all(|(layer, checkpoint_layer)| layer.has_same_state(checkpoint_layer))
+    }
+
+    /// Feed the input pulses to the layers receiving them and propagate the pulses produced
+    /// by each layer to the following ones, returning the pulses emitted by each layer
+    fn step_layers(&mut self, input: &[bool]) -> Vec<Vec<usize>> {
+        assert_eq!(
+            input.len(),
+            self.network.nr_inputs,
+            "the input must have one element for each entrance of the network"
+        );
+
+        //Time step duration converted to milliseconds to perform computation later
+        let time_step_duration_ms = self.network.time_step_duration_us / 1000.0;
+
+        // entrances of the network which received a pulse
+        let input_pulse_sources = input
+            .iter()
+            .enumerate()
+            .filter(|(_, pulse)| **pulse)
+            .map(|(input_node, _)| input_node)
+            .collect::<Vec<usize>>();
+
+        // pulses emitted by the layers during the previous time step, fed back to the
+        // layers receiving them
+        let time_step = self.network.time_step;
+        let fed_back_pulses = self.topology.fed_back_pulses(time_step, |layer_nr| {
+            &self.network.layers[layer_nr].emitted_pulse_sources
+        });
+
+        let mut emitted_pulses = Vec::<Vec<usize>>::with_capacity(self.network.layers.len());
+        for (layer_nr, layer) in self.network.layers.iter_mut().enumerate() {
+            let pulse_sources = self.topology.gather(
+                layer_nr,
+                &input_pulse_sources,
+                |source_layer_nr| &emitted_pulses[source_layer_nr],
+                |source_layer_nr| &fed_back_pulses[source_layer_nr],
+            );
+            let emitted_pulse_sources = layer.step(
+                &pulse_sources,
+                time_step,
+                time_step_duration_ms,
+                &self.neuron_models[layer_nr],
+                self.noise_sources[layer_nr].as_mut(),
+                self.network.faults.of_layer(layer_nr),
+            );
+            emitted_pulses.push(emitted_pulse_sources);
+        }
+
+        self.network.time_step += 1;
+        emitted_pulses
+    }
+
+    /// Build the output of the network from the pulses emitted by the last layer
+    fn output_from(&self, emitted_pulses: &[Vec<usize>]) -> Vec<bool> {
+        let mut output = vec![false; self.network.get_outputs_number().unwrap()];
+        if let Some(output_pulses) = emitted_pulses.last() {
+            for source_index in output_pulses {
+                output[*source_index] = true;
+            }
+        }
+        output
+    }
+}
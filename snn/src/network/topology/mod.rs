@@ -0,0 +1,189 @@
+use crate::network::neuron::Message;
+use crate::network::{LayerSource, Network};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// The Topology of a network describes how pulses flow through it: for each layer it
+/// keeps the sources the layer receives pulses from (see Layer::sources), each one
+/// together with its offset, i.e. the index of the synapse its first Neuron (or entrance)
+/// is connected to. The last layer of the network is its output.
+///
+/// Pulses fed back by a layer (see LayerSource::Feedback) are those it emitted during the
+/// previous time step: at time step 0 no pulse is fed back.
+#[derive(Clone)]
+pub struct Topology {
+    layer_sources: Vec<Vec<(LayerSource, usize)>>, // sources of each layer, with their offsets
+}
+
+/// Channels used by a layer during a run of the threaded engines: pulses emitted by each
+/// source of the layer are received from their own channel, with the offset of the source,
+/// while pulses emitted by the layer are sent to each layer receiving them, and to the
+/// output if the layer is the last one.
+/// Each channel also has the delay (in time steps) of the pulses it carries: 1 for the
+/// channels of feedback sources, 0 otherwise.
+pub(crate) struct LayerChannels {
+    pub(crate) receivers: Vec<(Receiver<Message>, usize, usize)>, // (receiver, offset, delay)
+    pub(crate) senders: Vec<(Sender<Message>, usize)>,            // (sender, delay)
+}
+
+/// Channels connecting the entrances, the layers and the output of a network
+pub(crate) struct Channels {
+    pub(crate) input_senders: Vec<Sender<Message>>, // feeding the layers receiving input pulses
+    pub(crate) layers: Vec<LayerChannels>,
+    pub(crate) output_receiver: Receiver<Message>, // receiving the pulses of the last layer
+}
+
+impl Topology {
+    /// Describe the topology of 'network'.
+    ///
+    /// Panics if a layer receives feedback from a layer which does not exist, or if a
+    /// convolutional layer does not receive pulses from as many sources as its input has
+    /// positions (see Convolution::input_len).
+    pub fn of(network: &Network) -> Self {
+        let layer_sources = (0..network.layers.len())
+            .map(|layer_nr| {
+                let mut offset = 0;
+                let sources = network
+                    .sources_of(layer_nr)
+                    .into_iter()
+                    .map(|source| {
+                        if let LayerSource::Feedback(source_layer_nr) = source {
+                            assert!(
+                                source_layer_nr < network.layers.len(),
+                                "a layer can only receive feedback from a layer of the network"
+                            );
+                        }
+                        let source_offset = offset;
+                        offset += network.source_len(source);
+                        (source, source_offset)
+                    })
+                    .collect();
+                if let Some(kernel) = &network.layers[layer_nr].kernel {
+                    assert_eq!(
+                        kernel.convolution.input_len(),
+                        offset,
+                        "the input of a convolutional layer must have a position for each \
+                         source of its pulses"
+                    );
+                }
+                sources
+            })
+            .collect();
+
+        Topology { layer_sources }
+    }
+
+    /// Get the sources of a layer, each one with its offset
+    pub fn sources(&self, layer_nr: usize) -> &[(LayerSource, usize)] {
+        &self.layer_sources[layer_nr]
+    }
+
+    /// Check whether a layer receives pulses from the specified source
+    pub fn receives_from(&self, layer_nr: usize, source: LayerSource) -> bool {
+        self.layer_sources[layer_nr]
+            .iter()
+            .any(|(layer_source, _)| *layer_source == source)
+    }
+
+    /// Check whether the pulses emitted by a layer are fed back to some layer
+    pub fn feeds_back(&self, layer_nr: usize) -> bool {
+        self.layer_sources
+            .iter()
+            .flatten()
+            .any(|(source, _)| *source == LayerSource::Feedback(layer_nr))
+    }
+
+    /// Collect the pulses fed back at the specified time step, i.e. those emitted by each
+    /// layer during the previous one, given a function returning the Neurons which fired in
+    /// each layer during its last update ('emitted_pulse_sources'). The Vec of a layer whose
+    /// pulses are not fed back is empty. This must be done before the layers are updated.
+    pub fn fed_back_pulses<'a>(
+        &self,
+        time_step: usize,
+        emitted_pulse_sources: impl Fn(usize) -> &'a [usize],
+    ) -> Vec<Vec<usize>> {
+        (0..self.layer_sources.len())
+            .map(|layer_nr| {
+                if time_step > 0 && self.feeds_back(layer_nr) {
+                    emitted_pulse_sources(layer_nr).to_vec()
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect()
+    }
+
+    /// Gather the sources of the pulses received by a layer during a time step, given the
+    /// entrances which received a pulse ('input_pulse_sources'), a function returning the
+    /// Neurons which fired in each earlier layer ('emitted_pulse_sources') and one returning
+    /// the pulses fed back by each layer ('fed_back_pulse_sources', see fed_back_pulses).
+    /// The indexes of the Neurons of each source are shifted by its offset, so that the
+    /// result is sorted like the pulses of the previous layer in a chain.
+    pub fn gather<'a>(
+        &self,
+        layer_nr: usize,
+        input_pulse_sources: &'a [usize],
+        emitted_pulse_sources: impl Fn(usize) -> &'a [usize],
+        fed_back_pulse_sources: impl Fn(usize) -> &'a [usize],
+    ) -> Vec<usize> {
+        let mut pulse_sources = Vec::new();
+        for (source, offset) in self.layer_sources[layer_nr].iter() {
+            let source_pulse_sources = match source {
+                LayerSource::Input => input_pulse_sources,
+                LayerSource::Layer(source_layer_nr) => emitted_pulse_sources(*source_layer_nr),
+                LayerSource::Feedback(source_layer_nr) => fed_back_pulse_sources(*source_layer_nr),
+            };
+            pulse_sources.extend(source_pulse_sources.iter().map(|index| index + offset));
+        }
+        pulse_sources
+    }
+
+    /// Create the channels connecting the layers according to the topology: a channel
+    /// for each (source, layer) pair, so that each source fans out its pulses to all the
+    /// layers receiving them and each layer fans in the pulses of all its sources, plus
+    /// the channel from the last layer to the output. Feedback channels go backwards as
+    /// well, but their pulses are received one time step later, so that layers do not wait
+    /// for each other in a cycle.
+    pub(crate) fn channels(&self) -> Channels {
+        let mut input_senders = Vec::new();
+        let mut layers = self
+            .layer_sources
+            .iter()
+            .map(|_| LayerChannels {
+                receivers: Vec::new(),
+                senders: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+
+        for (layer_nr, sources) in self.layer_sources.iter().enumerate() {
+            for (source, offset) in sources {
+                let (sender, receiver) = mpsc::channel();
+                let delay = match source {
+                    LayerSource::Input => {
+                        input_senders.push(sender);
+                        0
+                    }
+                    LayerSource::Layer(source_layer_nr) => {
+                        layers[*source_layer_nr].senders.push((sender, 0));
+                        0
+                    }
+                    LayerSource::Feedback(source_layer_nr) => {
+                        layers[*source_layer_nr].senders.push((sender, 1));
+                        1
+                    }
+                };
+                layers[layer_nr].receivers.push((receiver, *offset, delay));
+            }
+        }
+
+        let (output_sender, output_receiver) = mpsc::channel();
+        if let Some(last_layer) = layers.last_mut() {
+            last_layer.senders.push((output_sender, 0));
+        }
+
+        Channels {
+            input_senders,
+            layers,
+            output_receiver,
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use crate::network::dynamics::NeuronDynamics;
+use crate::network::layer::Layer;
+use crate::network::neuron::Message;
+use crate::network::noise::NoiseSource;
+use crate::network::probe::{Probes, Trace, TracedRun};
+use crate::network::topology::{LayerChannels, Topology};
+use crate::network::{Fault, Network, RegisterAddress};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle, ThreadId};
+
+/// A LayerRun holds everything a layer worker needs to simulate a layer of the network
+/// during a run: the layer itself, which is given back at the end of the run, and the
+/// settings inherited from the network.
+pub(crate) struct LayerRun {
+    layer: Layer,
+    layer_nr: usize,
+    neuron_models: Vec<Arc<dyn NeuronDynamics>>, // model resolved for each Neuron of the layer
+    noise_source: Option<NoiseSource>,           // noise source of the layer, if any
+    probes: Vec<(usize, RegisterAddress)>,       // probes belonging to the layer
+    faults: Vec<Fault>,                          // Faults affecting the layer
+    channels: LayerChannels,                     // channels connecting the layer to the others
+    first_time_step: usize,                      // time step of the network the run starts from
+    time_steps_number: usize,
+    time_step_duration_ms: f64,
+}
+
+/// Result of a LayerRun: the layer, with its updated state, and the values recorded by
+/// its probes
+pub(crate) type LayerRunResult = (Layer, Vec<Vec<f64>>);
+
+impl LayerRun {
+    /// Prepare the run of a layer which has been taken out of 'network', connected to
+    /// the other layers by 'channels'
+    pub(crate) fn new(
+        network: &Network,
+        layer: Layer,
+        layer_nr: usize,
+        channels: LayerChannels,
+        probes: &Probes,
+        time_steps_number: usize,
+    ) -> Self {
+        LayerRun {
+            neuron_models: layer.resolve_models(&network.model),
+            // each layer draws noise from its own source, so that results do not depend
+            // on thread scheduling
+            noise_source: network.noise.map(|noise| NoiseSource::new(noise, layer_nr)),
+            probes: probes.of_layer(layer_nr),
+            faults: network.faults.of_layer(layer_nr).to_vec(),
+            layer,
+            layer_nr,
+            channels,
+            first_time_step: network.time_step,
+            time_steps_number,
+            //Time step duration converted to milliseconds to perform computation later
+            time_step_duration_ms: network.time_step_duration_us / 1000.0,
+        }
+    }
+
+    /// Simulate the layer for all the time steps of the run, receiving the pulses emitted by
+    /// the sources of the layer from its receivers and sending the pulses emitted by the
+    /// layer to its senders (see LayerChannels).
+    ///
+    /// If another layer stops during the run, because it panicked, the channels connecting
+    /// them are disconnected: the layer stops as well, without panicking, so that only the
+    /// layer which panicked first reports its panic.
+    pub(crate) fn execute(mut self) -> LayerRunResult {
+        let mut probed_values = vec![Vec::new(); self.probes.len()];
+        let end_time_step = self.first_time_step + self.time_steps_number;
+
+        // the run continues a previous one: the pulses emitted by the layer during the last
+        // time step of that run are fed back at the first time step of this one
+        if self.first_time_step > 0 {
+            let time_step = self.first_time_step - 1;
+            for (sender, _) in self.channels.senders.iter().filter(|(_, delay)| *delay > 0) {
+                for source_index in self.layer.emitted_pulse_sources.iter().copied() {
+                    sender
+                        .send(Message::Pulse {
+                            time_step,
+                            source_index,
+                        })
+                        .ok();
+                }
+                sender.send(Message::GoAhead { time_step }).ok();
+            }
+        }
+
+        // each layer operates one time step at a time, in order. In order to perform
+        // computation for time step 'k', it is necessary that the layer has received all
+        // Pulses emitted during the SAME time step by each of its sources.
+        // In other words, layer 'n' can only process time step 'k' data when the layers it
+        // receives pulses from have terminated their own computation on the same time step 'k'
+        for time_step in self.first_time_step..end_time_step {
+            // Vec to keep track of the origin of each Pulse received during the current time step,
+            // i.e. the index of the neuron belonging to a source layer - or entrance - which generated
+            // the Pulse itself, shifted by the offset of the source; this is needed to allow the
+            // Neurons to choose the right Weight when computing the new Membrane Potential.
+            // Sources are read in order, so that pulses are gathered like by Topology::gather.
+            // Pulses received on a delayed (feedback) channel belong to an earlier time step
+            let mut pulse_sources = Vec::new();
+            for (receiver, offset, delay) in self.channels.receivers.iter() {
+                if time_step < *delay {
+                    continue;
+                }
+                let Some(source_pulse_sources) = Network::receive_time_step_pulses(
+                    receiver,
+                    time_step - delay,
+                    &format!("layer {}", self.layer_nr),
+                ) else {
+                    return (self.layer, probed_values);
+                };
+                pulse_sources.extend(source_pulse_sources.into_iter().map(|index| index + offset));
+            }
+
+            // Update the status for the layer Neurons and send the pulses produced by
+            // them over the channels to the following layers
+            let emitted_pulse_sources = self.layer.step(
+                &pulse_sources,
+                time_step,
+                self.time_step_duration_ms,
+                &self.neuron_models,
+                self.noise_source.as_mut(),
+                &self.faults,
+            );
+            for (sender, delay) in self.channels.senders.iter() {
+                // pulses delayed beyond the end of the run are not received by anyone
+                if time_step + delay >= end_time_step {
+                    continue;
+                }
+                // a layer which stopped receiving does not need the pulses anymore
+                for source_index in emitted_pulse_sources.iter().copied() {
+                    sender
+                        .send(Message::Pulse {
+                            time_step,
+                            source_index,
+                        })
+                        .ok();
+                }
+
+                // Signal to the following layer that all pulses for this time step
+                // have been sent, by sending a GoAhead Control Message. This is done
+                // even if the layer produced no pulses, so that layers stay aligned
+                sender.send(Message::GoAhead { time_step }).ok();
+            }
+
+            // record the values of the probed Registers
+            Probes::record(
+                &self.probes,
+                &mut self.layer,
+                &self.faults,
+                time_step,
+                &mut probed_values,
+            );
+        }
+
+        // give the layer back, so that its state is kept inside the network,
+        // together with the values recorded by its probes
+        (self.layer, probed_values)
+    }
+}
+
+/// A WorkerPool keeps one worker thread for each layer of a network alive across
+/// multiple runs, so that threads are spawned only once, e.g. for a whole fault
+/// injection campaign, instead of once per run.
+///
+/// At the beginning of each run every worker receives, through its own job channel, the
+/// layer it has to simulate (i.e. the new network state), together with the channels
+/// connecting it to the other layers according to the Topology of the network, and then
+/// processes the pulses flowing through them like the threaded engine does. At the end
+/// of the run the layers are given back to the network.
+///
+/// A worker which panics during a run reports the panic instead of the layer, and keeps
+/// waiting for the following runs.
+pub struct WorkerPool {
+    job_senders: Vec<Sender<LayerRun>>, // channels used to start a run on each worker
+    // channels used to collect the layers back, or the panics of the workers
+    result_receivers: Vec<Receiver<thread::Result<LayerRunResult>>>,
+    thread_handles: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Spawn the workers for a network with the specified number of layers
+    pub fn new(number_of_layers: usize) -> Self {
+        let mut job_senders = Vec::with_capacity(number_of_layers);
+        let mut result_receivers = Vec::with_capacity(number_of_layers);
+        let mut thread_handles = Vec::with_capacity(number_of_layers);
+
+        for layer_nr in 0..number_of_layers {
+            let (job_sender, job_receiver) = mpsc::channel::<LayerRun>();
+            let (result_sender, result_receiver) = mpsc::channel();
+
+            let join_handle = thread::Builder::new()
+                .name(format!("layer {} worker", layer_nr))
+                .spawn(move || {
+                    // perform runs until the pool is dropped. A panic only ends the run:
+                    // dropping the channels of the layer makes the other workers end it too
+                    while let Ok(layer_run) = job_receiver.recv() {
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| layer_run.execute()));
+                        if result_sender.send(result).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .unwrap();
+
+            job_senders.push(job_sender);
+            result_receivers.push(result_receiver);
+            thread_handles.push(join_handle);
+        }
+
+        WorkerPool {
+            job_senders,
+            result_receivers,
+            thread_handles,
+        }
+    }
+
+    /// Get the number of layer workers of the pool
+    pub fn len(&self) -> usize {
+        self.job_senders.len()
+    }
+
+    /// Check whether the pool has no workers
+    pub fn is_empty(&self) -> bool {
+        self.job_senders.is_empty()
+    }
+
+    /// Get the ids of the worker threads, one for each layer
+    pub fn thread_ids(&self) -> Vec<ThreadId> {
+        self.thread_handles
+            .iter()
+            .map(|join_handle| join_handle.thread().id())
+            .collect()
+    }
+
+    /// Simulate 'network' like Network::run_with_probes, using the workers of the pool.
+    /// The pool must have one worker for each layer of the network. At the end of the
+    /// run each layer keeps its state, like with Network::run_in_place.
+    ///
+    /// If a worker panics during the run, the panic is propagated once all the workers
+    /// ended the run, leaving the network in the state it had before the run.
+    /// Panics if a worker terminated.
+    pub fn run(&self, network: &mut Network, input: &[Vec<bool>], probes: &Probes) -> TracedRun {
+        assert_eq!(
+            self.len(),
+            network.layers.len(),
+            "the pool must have one worker for each layer of the network"
+        );
+
+        // Number of time steps to simulate in the SNN, equal to the number of columns
+        // of the 'input' matrix
+        let snn_time_steps_number = input[0].len();
+
+        // boolean matrix holding the result of the simulation (see Network::run_with_probes)
+        let mut output = network.create_output_matrix(snn_time_steps_number);
+        let mut trace = Trace::new(probes);
+
+        let channels = Topology::of(network).channels();
+
+        // the input is buffered by the channels before the workers start, so that a worker
+        // panicking early cannot disconnect them while it is being sent
+        let first_time_step = network.time_step;
+        Network::inject_input(&channels.input_senders, input, first_time_step);
+
+        // hand a copy of each layer over to its worker, which starts waiting for pulses:
+        // the network keeps its layers until the run succeeds
+        for (layer_nr, (layer, layer_channels)) in network
+            .layers
+            .iter()
+            .cloned()
+            .zip(channels.layers)
+            .enumerate()
+        {
+            let layer_run = LayerRun::new(
+                network,
+                layer,
+                layer_nr,
+                layer_channels,
+                probes,
+                snn_time_steps_number,
+            );
+            self.job_senders[layer_nr]
+                .send(layer_run)
+                .unwrap_or_else(|_| panic!("layer {layer_nr} worker terminated"));
+        }
+
+        // collect the results of all the workers, so that none is left for the next run
+        let results = self
+            .result_receivers
+            .iter()
+            .enumerate()
+            .map(|(layer_nr, result_receiver)| {
+                result_receiver
+                    .recv()
+                    .unwrap_or_else(|_| panic!("layer {layer_nr} worker terminated"))
+            })
+            .collect::<Vec<_>>();
+
+        // put the layers back, together with the values recorded by their probes.
+        // If a layer panicked, the first panic is propagated
+        let mut layers = Vec::with_capacity(results.len());
+        for (layer_nr, result) in results.into_iter().enumerate() {
+            let (layer, probed_values) = result.unwrap_or_else(|panic| panic::resume_unwind(panic));
+            layers.push(layer);
+            trace.collect(probes, layer_nr, probed_values);
+        }
+        network.layers = layers;
+        trace.fill_missing_layers(network.layers.len(), snn_time_steps_number);
+
+        // write results to the output boolean matrix
+        Network::write_results(&channels.output_receiver, first_time_step, &mut output);
+        network.time_step += snn_time_steps_number;
+
+        TracedRun { output, trace }
+    }
+}
+
+impl Drop for WorkerPool {
+    /// Stop the workers, waiting for their termination
+    fn drop(&mut self) {
+        // closing the job channels makes the workers exit their loop
+        self.job_senders.clear();
+        for join_handle in self.thread_handles.drain(..) {
+            // a worker which panicked has already reported its error
+            let _ = join_handle.join();
+        }
+    }
+}
+
+/// A PoolSlot keeps the WorkerPool of a network between its runs with the pipelined engine:
+/// the pool is spawned by the first run and reused by the following ones. A pool can only
+/// run one network at a time, so clones of the network do not share it: the slot of a
+/// clone is empty. The Mutex is never contended, it only lets networks be shared among
+/// threads (see Network::run_batch).
+#[derive(Default)]
+pub(crate) struct PoolSlot(Mutex<Option<WorkerPool>>);
+
+impl Clone for PoolSlot {
+    fn clone(&self) -> Self {
+        PoolSlot::default()
+    }
+}
+
+impl PoolSlot {
+    /// Take the pool out of the slot, spawning a new one if the slot is empty or if the
+    /// pool has not one worker for each of the 'number_of_layers' layers
+    pub(crate) fn take(&mut self, number_of_layers: usize) -> WorkerPool {
+        self.0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+            .filter(|pool| pool.len() == number_of_layers)
+            .unwrap_or_else(|| WorkerPool::new(number_of_layers))
+    }
+
+    /// Put the pool back in the slot, so that it is reused by the next run
+    pub(crate) fn put(&mut self, pool: WorkerPool) {
+        *self.0.get_mut().unwrap_or_else(PoisonError::into_inner) = Some(pool);
+    }
+
+    /// Get the ids of the worker threads of the pool in the slot, if any
+    pub(crate) fn thread_ids(&self) -> Vec<ThreadId> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(WorkerPool::thread_ids)
+            .unwrap_or_default()
+    }
+}
@@ -44,6 +44,11 @@ impl Register {
         self.damage = damage;
     }
 
+    /// return the damage applied to the register
+    pub fn damage(&self) -> Damage {
+        self.damage
+    }
+
     /// write the provided value to the register.
     /// 'Damages', if present, are applied each time ONLY to the returned copy of the
     /// value when performing a reading, so, leaving the original unchanged
@@ -51,6 +56,12 @@ impl Register {
         self.value = value;
     }
 
+    /// return the value stored inside the register, ignoring damages. This does not
+    /// model a hardware operation: it is meant to inspect or export the register content
+    pub fn stored_value(&self) -> f64 {
+        self.value
+    }
+
     /// read and return the value contained inside the register: damages inside
     /// the register are automatically applied, if present.
     /// current_time_step can be set to None unless TransientBitFlip is
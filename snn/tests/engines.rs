@@ -0,0 +1,1152 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use snn::network::convolution::Convolution;
+use snn::network::dynamics::NeuronDynamics;
+use snn::network::json::{self, InputMatrix};
+use snn::network::layer::Layer;
+use snn::network::neuron::{Neuron, NeuronRegister};
+use snn::network::noise::NoiseModel;
+use snn::network::probe::{Probes, Trace};
+use snn::network::session::StepState;
+use snn::network::worker::WorkerPool;
+use snn::network::{
+    DamageModel, Engine, Fault, FaultyElement, LateralInhibition, LayerSource, Network,
+    NeuronParallelism, RegisterAddress,
+};
+use snn::register::{Damage, Register};
+use std::sync::Arc;
+
+const NETWORK_JSON: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/sources/snn_data.json");
+const INPUT_JSON: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/sources/simulation_input.json");
+
+/// Run a copy of 'network' with the specified engine, optionally applying a fault
+fn run_with_engine(network: &Network, engine: Engine, fault: Option<Fault>) -> Vec<Vec<bool>> {
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let mut snn = network.clone();
+    snn.set_engine(engine);
+    if let Some(fault) = fault {
+        assert!(snn.apply_damage(fault));
+    }
+    snn.run(input)
+}
+
+/// Faults covering every kind of Register and Damage of the first Neuron of each layer
+fn faults(network: &Network) -> Vec<Fault> {
+    let registers = [
+        NeuronRegister::Weight(0),
+        NeuronRegister::InternalWeight(1),
+        NeuronRegister::Threshold,
+        NeuronRegister::MembranePotential,
+        NeuronRegister::ResetPotential,
+        NeuronRegister::PotentialAtRest,
+        NeuronRegister::Bias,
+        NeuronRegister::Comparator,
+        NeuronRegister::Adder,
+        NeuronRegister::Multiplier,
+        NeuronRegister::Divider,
+    ];
+
+    let mut faults = Vec::new();
+    for layer in 0..network.layers.len() {
+        for register in registers {
+            for bit_position in [52, 62, 63] {
+                for damage in [
+                    Damage::StuckAt0 { bit_position },
+                    Damage::StuckAt1 { bit_position },
+                    Damage::TransientBitFlip {
+                        bit_position,
+                        time_step: 2,
+                    },
+                ] {
+                    faults.push(Fault {
+                        address: RegisterAddress {
+                            layer,
+                            neuron: 0,
+                            register,
+                        },
+                        damage,
+                    });
+                }
+            }
+        }
+    }
+    faults
+}
+
+#[test]
+fn engines_produce_identical_output() {
+    let network = json::load_from_file(NETWORK_JSON);
+    let expected = run_with_engine(&network, Engine::Threaded, None);
+
+    assert!(expected == run_with_engine(&network, Engine::Sequential, None));
+    assert!(expected == run_with_engine(&network, Engine::Pipelined, None));
+    assert!(expected == run_with_engine(&network, Engine::Packed, None));
+}
+
+#[test]
+fn engines_produce_identical_fault_results() {
+    let network = json::load_from_file(NETWORK_JSON);
+
+    for fault in faults(&network) {
+        let expected = run_with_engine(&network, Engine::Threaded, Some(fault));
+        for engine in [Engine::Sequential, Engine::Pipelined, Engine::Packed] {
+            assert!(
+                expected == run_with_engine(&network, engine, Some(fault)),
+                "engines disagree on fault at layer {}, neuron {}, register {}",
+                fault.address.layer,
+                fault.address.neuron,
+                fault.address.register.name()
+            );
+        }
+    }
+}
+
+#[test]
+fn faults_leave_synapse_registers_shared() {
+    let network = json::load_from_file(NETWORK_JSON);
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+
+    for register in [NeuronRegister::Weight(0), NeuronRegister::InternalWeight(1)] {
+        for engine in [Engine::Sequential, Engine::Threaded, Engine::Pipelined] {
+            let mut snn = network.clone();
+            snn.set_engine(engine);
+            assert!(snn.apply_damage(Fault {
+                address: RegisterAddress {
+                    layer: 1,
+                    neuron: 0,
+                    register,
+                },
+                damage: Damage::StuckAt1 { bit_position: 62 },
+            }));
+            snn.run_in_place(&input);
+
+            // the damaged Neuron still shares its synapse Vecs with the original network
+            let (neuron, original_neuron) =
+                (&snn.layers[1].neurons[0], &network.layers[1].neurons[0]);
+            assert!(Arc::ptr_eq(&neuron.weights, &original_neuron.weights));
+            assert!(Arc::ptr_eq(
+                &neuron.internal_weights,
+                &original_neuron.internal_weights
+            ));
+        }
+    }
+}
+
+/// The network of the fixture with weaker lateral inhibition, so that it keeps firing
+/// after the first time step and faults affecting later time steps can be observed
+fn active_network() -> Network {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    for neuron in network
+        .layers
+        .iter_mut()
+        .flat_map(|layer| layer.neurons.iter_mut())
+    {
+        let internal_weights = neuron
+            .internal_weights
+            .iter()
+            .map(|weight| weight.stored_value() * 0.3)
+            .collect();
+        neuron.set_internal_weights(internal_weights);
+    }
+    network
+}
+
+#[test]
+fn checkpointed_runs_match_full_runs() {
+    let network = active_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let last_time_step = input[0].len() - 1;
+
+    let mut snn = network.clone();
+    snn.set_checkpointing(true);
+    let golden_run = snn.run_with_checkpoints(&input);
+    assert!(golden_run.output == run_with_engine(&network, Engine::Sequential, None));
+
+    // transient faults at the first, a middle and the last time step
+    for time_step in [0, last_time_step / 2, last_time_step] {
+        for fault in faults(&network) {
+            let Damage::TransientBitFlip { bit_position, .. } = fault.damage else {
+                continue;
+            };
+            let fault = Fault {
+                damage: Damage::TransientBitFlip {
+                    bit_position,
+                    time_step,
+                },
+                ..fault
+            };
+
+            snn.reset_state();
+            let faulty_run = snn.resume_with_fault(&golden_run, &input, fault);
+            assert!(faulty_run.masked_at.is_none());
+            for engine in [Engine::Sequential, Engine::Threaded] {
+                assert!(faulty_run.output == run_with_engine(&network, engine, Some(fault)));
+            }
+        }
+    }
+}
+
+#[test]
+fn early_termination_matches_full_runs() {
+    let network = active_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+
+    let mut snn = network.clone();
+    snn.set_early_termination(true);
+    let golden_run = snn.run_with_checkpoints(&input);
+
+    for fault in faults(&network) {
+        let faulty_run = snn.resume_with_fault(&golden_run, &input, fault);
+        assert!(faulty_run.output == run_with_engine(&network, Engine::Sequential, Some(fault)));
+        // a stuck-at fault is always active, so it is never masked
+        if !matches!(fault.damage, Damage::TransientBitFlip { .. }) {
+            assert!(faulty_run.masked_at.is_none());
+        }
+    }
+
+    let bit_flip = |register, bit_position| Fault {
+        address: RegisterAddress {
+            layer: 0,
+            neuron: 0,
+            register,
+        },
+        damage: Damage::TransientBitFlip {
+            bit_position,
+            time_step: 2,
+        },
+    };
+
+    // the flipped adder output is overwritten at the following time step
+    let fault = bit_flip(NeuronRegister::Adder, 62);
+    let faulty_run = snn.resume_with_fault(&golden_run, &input, fault);
+    assert!(faulty_run.masked_at == Some(3));
+    assert!(faulty_run.output == golden_run.output);
+
+    // the flipped membrane potential changes the pulses emitted afterwards
+    let fault = bit_flip(NeuronRegister::MembranePotential, 52);
+    let faulty_run = snn.resume_with_fault(&golden_run, &input, fault);
+    assert!(faulty_run.masked_at.is_none());
+    assert!(faulty_run.output != golden_run.output);
+    assert!(faulty_run.output == run_with_engine(&network, Engine::Sequential, Some(fault)));
+}
+
+#[test]
+fn worker_pool_is_reused_across_runs() {
+    let network = json::load_from_file(NETWORK_JSON);
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let pool = WorkerPool::new(network.layers.len());
+
+    // the same workers run all the faults, starting each time from the initial state
+    let mut snn = network.clone();
+    for fault in faults(&network) {
+        snn.reset_state();
+        assert!(snn.apply_damage(fault));
+        let output = pool.run(&mut snn, &input, &Probes::new()).output;
+        snn.remove_damage(fault.address);
+
+        assert!(output == run_with_engine(&network, Engine::Threaded, Some(fault)));
+    }
+}
+
+#[test]
+fn pipelined_engine_reuses_workers_across_runs() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    network.set_engine(Engine::Pipelined);
+    assert!(network.worker_threads().is_empty());
+
+    let output = network.run_in_place(&input);
+    let worker_threads = network.worker_threads();
+    assert!(worker_threads.len() == network.layers.len());
+
+    // the following runs are performed by the same workers, a clone spawns its own ones
+    network.reset_state();
+    assert!(output == network.run_in_place(&input));
+    assert!(worker_threads == network.worker_threads());
+    assert!(network.clone().worker_threads().is_empty());
+}
+
+/// A neuron model behaving like CustomIntegrateAndFire which panics at a time step
+struct FailingIntegrateAndFire {
+    failing_time_step: usize,
+}
+
+impl NeuronDynamics for FailingIntegrateAndFire {
+    fn update_membrane_potential(
+        &self,
+        neuron: &mut Neuron,
+        pulses_contribution: Register,
+        time_step: usize,
+        time_step_duration_ms: f64,
+    ) {
+        if time_step == self.failing_time_step {
+            panic!("neuron model failure");
+        }
+        CustomIntegrateAndFire.update_membrane_potential(
+            neuron,
+            pulses_contribution,
+            time_step,
+            time_step_duration_ms,
+        );
+    }
+}
+
+#[test]
+fn worker_panics_leave_the_network_unchanged() {
+    let network = active_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let expected = network.clone().run(input.clone());
+    let membrane_potentials = |network: &Network| {
+        network
+            .layers
+            .iter()
+            .flat_map(|layer| {
+                layer
+                    .neurons
+                    .iter()
+                    .map(|neuron| neuron.v_mem.stored_value())
+            })
+            .collect::<Vec<f64>>()
+    };
+
+    let mut snn = network.clone();
+    snn.set_engine(Engine::Pipelined);
+    snn.run_in_place(&input);
+    snn.reset_state();
+    assert!(!snn.worker_threads().is_empty());
+    snn.layers[1].neurons[0].model = Some(Arc::new(FailingIntegrateAndFire {
+        failing_time_step: 3,
+    }));
+
+    // the panic of the worker is propagated and the network keeps its state
+    let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| snn.run_in_place(&input)))
+        .unwrap_err();
+    assert!(panic.downcast_ref::<&str>() == Some(&"neuron model failure"));
+    assert!(snn.layers.len() == network.layers.len());
+    assert!(membrane_potentials(&snn) == membrane_potentials(&network));
+    assert!(snn.time_step() == 0);
+
+    // the workers of the failed run are not reused
+    assert!(snn.worker_threads().is_empty());
+    snn.layers[1].neurons[0].model = None;
+    assert!(snn.run_in_place(&input) == expected);
+
+    // a pool whose worker panicked can still run the network
+    let pool = WorkerPool::new(network.layers.len());
+    let mut snn = network.clone();
+    snn.layers[1].neurons[0].model = Some(Arc::new(FailingIntegrateAndFire {
+        failing_time_step: 3,
+    }));
+    let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        pool.run(&mut snn, &input, &Probes::new())
+    }))
+    .err()
+    .unwrap();
+    assert!(panic.downcast_ref::<&str>() == Some(&"neuron model failure"));
+    snn.layers[1].neurons[0].model = None;
+    assert!(pool.run(&mut snn, &input, &Probes::new()).output == expected);
+}
+
+#[test]
+fn engines_produce_identical_output_with_noise() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    network.set_noise(NoiseModel {
+        membrane_noise_std: 2.0,
+        escape_noise_width: Some(1.0),
+        seed: 42,
+    });
+
+    let expected = run_with_engine(&network, Engine::Threaded, None);
+
+    assert!(expected == run_with_engine(&network, Engine::Sequential, None));
+    assert!(expected == run_with_engine(&network, Engine::Pipelined, None));
+    assert!(expected == run_with_engine(&network, Engine::Packed, None));
+}
+
+#[test]
+fn concurrent_engine_produces_identical_fault_results() {
+    let network = json::load_from_file(NETWORK_JSON);
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let faults = faults(&network);
+
+    let concurrent_run = network.clone().run_concurrently(&input, &faults);
+
+    assert!(concurrent_run.output == run_with_engine(&network, Engine::Threaded, None));
+    for (fault, faulty_run) in faults.iter().zip(concurrent_run.faulty_runs) {
+        assert!(faulty_run.output == run_with_engine(&network, Engine::Threaded, Some(*fault)));
+    }
+}
+
+#[test]
+fn concurrent_engine_matches_while_faulty_machines_diverge() {
+    // the network keeps firing, so that the machines keep diverging after the first time step
+    let mut network = active_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let faults = faults(&network);
+
+    for inhibition in [
+        LateralInhibition::Dense,
+        LateralInhibition::Uniform { strength: 0.3 },
+        LateralInhibition::KWinnerTakeAll { k: 2 },
+    ] {
+        for layer in network.layers.iter_mut() {
+            layer.inhibition = inhibition;
+        }
+
+        let concurrent_run = network.clone().run_concurrently(&input, &faults);
+
+        assert!(concurrent_run.output == run_with_engine(&network, Engine::Threaded, None));
+        for (fault, faulty_run) in faults.iter().zip(concurrent_run.faulty_runs) {
+            assert!(faulty_run.output == run_with_engine(&network, Engine::Threaded, Some(*fault)));
+        }
+    }
+}
+
+#[test]
+fn batch_outputs_match_individual_runs() {
+    let network = json::load_from_file(NETWORK_JSON);
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+
+    // samples obtained rotating the input by a different number of time steps
+    let inputs = (0..input[0].len())
+        .map(|shift| {
+            input
+                .iter()
+                .map(|entrance| {
+                    let mut entrance = entrance.clone();
+                    entrance.rotate_left(shift);
+                    entrance
+                })
+                .collect::<Vec<Vec<bool>>>()
+        })
+        .collect::<Vec<Vec<Vec<bool>>>>();
+
+    let mut damaged_network = network.clone();
+    assert!(damaged_network.apply_damage(faults(&network)[0]));
+
+    for snn in [network, damaged_network] {
+        let expected = inputs
+            .iter()
+            .map(|input| snn.clone().run(input.clone()))
+            .collect::<Vec<Vec<Vec<bool>>>>();
+
+        assert!(expected == snn.run_batch(&inputs, 1));
+        assert!(expected == snn.run_batch(&inputs, 4));
+    }
+}
+
+#[test]
+fn engines_produce_identical_output_with_sparse_synapses() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    // keeping only the synapses coming from Neurons with an even index
+    for layer in network.layers.iter_mut() {
+        for neuron in layer.neurons.iter_mut() {
+            let synapses = neuron
+                .weights
+                .iter()
+                .enumerate()
+                .filter(|(source_index, _)| source_index % 2 == 0)
+                .map(|(source_index, weight)| (source_index, weight.stored_value()))
+                .collect();
+            neuron.set_synapses(synapses);
+        }
+    }
+
+    let expected = run_with_engine(&network, Engine::Threaded, None);
+    for engine in [Engine::Sequential, Engine::Pipelined, Engine::Packed] {
+        assert!(expected == run_with_engine(&network, engine, None));
+    }
+
+    for fault in faults(&network) {
+        let expected = run_with_engine(&network, Engine::Threaded, Some(fault));
+        assert!(expected == run_with_engine(&network, Engine::Packed, Some(fault)));
+    }
+}
+
+#[test]
+fn weight_campaign_damages_existing_synapses_only() {
+    let mut network = active_network();
+    // the first Neuron of the first layer has no synapse at all, the other ones only those
+    // coming from entrances with an even index
+    for (i, neuron) in network.layers[0].neurons.iter_mut().enumerate() {
+        let synapses = neuron
+            .weights
+            .iter()
+            .enumerate()
+            .filter(|(source_index, _)| i > 0 && source_index % 2 == 0)
+            .map(|(source_index, weight)| (source_index, weight.stored_value()))
+            .collect();
+        neuron.set_synapses(synapses);
+    }
+    // the second layer uses uniform inhibition, so its Neurons need no internal weights
+    network.layers[1].inhibition = LateralInhibition::Uniform { strength: 1.0 };
+    for neuron in network.layers[1].neurons.iter_mut() {
+        neuron.set_internal_weights(Vec::new());
+    }
+
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    for engine in [Engine::Sequential, Engine::Threaded] {
+        network.set_engine(engine);
+        let result = network
+            .simulate(
+                vec![FaultyElement::Weights],
+                DamageModel::StuckAt1,
+                1000,
+                input.clone(),
+            )
+            .unwrap();
+        assert!(result.number_of_iterations == 1000);
+
+        // the damages which changed the output: the network keeps firing, so that many do
+        let damage_details = result
+            .diffs
+            .iter()
+            .flatten()
+            .flat_map(|cell| cell.damage_details.iter())
+            .collect::<Vec<_>>();
+        assert!(!damage_details.is_empty());
+        for damage_detail in damage_details {
+            let neuron = &network.layers[damage_detail.at_layer].neurons[damage_detail.at_neuron];
+            match (damage_detail.at_layer, damage_detail.at_register) {
+                // the weight of an existing synapse, coming from an even entrance
+                (0, Some(NeuronRegister::Weight(index))) => {
+                    assert!(damage_detail.at_neuron != 0);
+                    let synapse_sources = neuron.synapse_sources.as_ref().unwrap();
+                    assert!(synapse_sources
+                        .get(index)
+                        .is_some_and(|source_index| source_index % 2 == 0));
+                }
+                // no internal weight is read by uniform inhibition
+                (1, Some(NeuronRegister::Weight(index))) => assert!(index < neuron.weights.len()),
+                (2, Some(NeuronRegister::Weight(index))) => assert!(index < neuron.weights.len()),
+                (2, Some(NeuronRegister::InternalWeight(index))) => {
+                    assert!(index < neuron.internal_weights.len())
+                }
+                _ => panic!("a damage which does not affect any synapse changed the output"),
+            }
+        }
+    }
+}
+
+#[test]
+fn campaigns_refuse_checkpointing_with_engines_not_using_it() {
+    let mut network = active_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let campaign = |network: &Network| {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            network.simulate(
+                vec![FaultyElement::Thresholds],
+                DamageModel::TransientBitFlip,
+                10,
+                input.clone(),
+            )
+        }))
+    };
+
+    for (checkpointing, early_termination) in [(true, false), (false, true)] {
+        network.set_checkpointing(checkpointing);
+        network.set_early_termination(early_termination);
+        for engine in [Engine::Sequential, Engine::Concurrent] {
+            network.set_engine(engine);
+            assert!(campaign(&network).unwrap().is_some());
+        }
+        for engine in [Engine::Threaded, Engine::Pipelined, Engine::Packed] {
+            network.set_engine(engine);
+            let panic = campaign(&network).err().unwrap();
+            assert!(
+                panic.downcast_ref::<&str>()
+                    == Some(
+                        &"checkpointing and early termination require the sequential or \
+                        concurrent engine"
+                    )
+            );
+        }
+    }
+}
+
+/// Make each layer of 'network' receive pulses from the specified sources (None for the
+/// previous layer): the synapses from the new sources reuse the weights of the existing
+/// ones, scaled down so that the output does not saturate
+fn connect_sources(network: &mut Network, layer_sources: Vec<Option<Vec<LayerSource>>>) {
+    for (layer_nr, sources) in layer_sources.into_iter().enumerate() {
+        network.layers[layer_nr].sources = sources;
+        let synapses_number = network
+            .sources_of(layer_nr)
+            .into_iter()
+            .map(|source| network.source_len(source))
+            .sum::<usize>();
+        for neuron in network.layers[layer_nr].neurons.iter_mut() {
+            let existing_synapses = neuron.weights.len();
+            let weights = neuron
+                .weights
+                .iter()
+                .cycle()
+                .take(synapses_number)
+                .enumerate()
+                .map(|(i, weight)| {
+                    if i < existing_synapses {
+                        weight.stored_value()
+                    } else {
+                        weight.stored_value() * 0.25
+                    }
+                })
+                .collect();
+            neuron.set_weights(weights);
+        }
+    }
+}
+
+/// Check that all engines produce the same output for 'network', both without damages and
+/// with each of the provided faults
+fn assert_engines_agree(network: &Network, faults: &[Fault]) {
+    let expected = run_with_engine(network, Engine::Threaded, None);
+    for engine in [Engine::Sequential, Engine::Pipelined, Engine::Packed] {
+        assert!(expected == run_with_engine(network, engine, None));
+    }
+
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let concurrent_run = network.clone().run_concurrently(&input, faults);
+    assert!(concurrent_run.output == expected);
+    for (fault, faulty_run) in faults.iter().zip(concurrent_run.faulty_runs) {
+        let expected = run_with_engine(network, Engine::Threaded, Some(*fault));
+        assert!(faulty_run.output == expected);
+        assert!(expected == run_with_engine(network, Engine::Packed, Some(*fault)));
+    }
+}
+
+#[test]
+fn engines_produce_identical_output_with_skip_connections() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    // layer 1 also receives the input pulses, layer 2 those of the entrances and of layer 0
+    connect_sources(
+        &mut network,
+        vec![
+            None,
+            Some(vec![LayerSource::Layer(0), LayerSource::Input]),
+            Some(vec![
+                LayerSource::Layer(1),
+                LayerSource::Input,
+                LayerSource::Layer(0),
+            ]),
+        ],
+    );
+
+    assert_engines_agree(&network, &faults(&network));
+}
+
+#[test]
+fn engines_produce_identical_output_with_feedback() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    // the pulses of the last layer are fed back to the first one, those of layer 1 to itself
+    connect_sources(
+        &mut network,
+        vec![
+            Some(vec![LayerSource::Input, LayerSource::Feedback(2)]),
+            Some(vec![LayerSource::Layer(0), LayerSource::Feedback(1)]),
+            None,
+        ],
+    );
+
+    // faults also affect the weights of the feedback synapses
+    let mut faults = faults(&network);
+    for (layer, register) in [
+        (0, NeuronRegister::Weight(network.nr_inputs)),
+        (1, NeuronRegister::Weight(network.layers[0].len())),
+    ] {
+        for bit_position in [52, 62, 63] {
+            faults.push(Fault {
+                address: RegisterAddress {
+                    layer,
+                    neuron: 0,
+                    register,
+                },
+                damage: Damage::StuckAt1 { bit_position },
+            });
+        }
+    }
+
+    assert_engines_agree(&network, &faults);
+}
+
+/// Replace the first layer of the network with a convolutional one: the entrances are seen
+/// as a 2x3 image, over which two 2x2 kernels are slid with padding 1, giving two feature
+/// maps of 3x4 Neurons. The Neurons and the kernel reuse the parameters of the first
+/// Neuron of the layer, and the second layer is connected to all of them.
+fn convolutional_network() -> Network {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    let convolution = Convolution {
+        input_channels: 1,
+        input_height: 2,
+        input_width: 3,
+        output_channels: 2,
+        kernel_size: 2,
+        stride: 1,
+        padding: 1,
+    };
+
+    let template = network.layers[0].neurons[0].clone();
+    let cycled_values = |registers: &[Register], len: usize| {
+        registers
+            .iter()
+            .cycle()
+            .take(len)
+            .map(|register| register.stored_value())
+            .collect::<Vec<f64>>()
+    };
+    let kernel = cycled_values(&template.weights, convolution.kernel_len());
+    let internal_weights = cycled_values(&template.internal_weights, convolution.output_len());
+    let neurons = (0..convolution.output_len())
+        .map(|_| {
+            let mut neuron = template.clone();
+            neuron.set_internal_weights(internal_weights.clone());
+            neuron
+        })
+        .collect();
+    network.layers[0] = Layer::convolutional(neurons, convolution, kernel);
+
+    for neuron in network.layers[1].neurons.iter_mut() {
+        neuron.set_weights(cycled_values(&neuron.weights, convolution.output_len()));
+    }
+    network
+}
+
+#[test]
+fn engines_produce_identical_output_with_convolution() {
+    let network = convolutional_network();
+
+    // faults also affect the kernel through Neurons other than the first one
+    let mut faults = faults(&network);
+    for (neuron, register) in [
+        (5, NeuronRegister::Weight(3)),
+        (17, NeuronRegister::Weight(6)),
+    ] {
+        for bit_position in [52, 62, 63] {
+            faults.push(Fault {
+                address: RegisterAddress {
+                    layer: 0,
+                    neuron,
+                    register,
+                },
+                damage: Damage::StuckAt1 { bit_position },
+            });
+        }
+    }
+
+    assert_engines_agree(&network, &faults);
+}
+
+#[test]
+fn kernel_fault_affects_every_output_position() {
+    let network = convolutional_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let mut probes = Probes::new();
+    probes.probe_layer(&network, 0, NeuronRegister::MembranePotential);
+
+    // the weight is damaged through the first Neuron, but it is shared by the whole
+    // feature map
+    let fault = Fault {
+        address: RegisterAddress {
+            layer: 0,
+            neuron: 0,
+            register: NeuronRegister::Weight(3),
+        },
+        damage: Damage::StuckAt1 { bit_position: 62 },
+    };
+    let (golden, faulty) = network.trace_fault(fault, &input, &probes);
+
+    // the Neurons of the first feature map, which come first, all use the damaged weight
+    let feature_map_len = 12;
+    assert!(golden
+        .trace
+        .values
+        .iter()
+        .zip(&faulty.trace.values)
+        .take(feature_map_len)
+        .all(|(golden_values, faulty_values)| golden_values != faulty_values));
+}
+
+#[test]
+fn bias_makes_neurons_fire_without_input() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    let last_layer = network.layers.len() - 1;
+    let neuron = &mut network.layers[last_layer].neurons[0];
+    let bias = 2.0 * (neuron.v_th.stored_value() - neuron.v_rest.stored_value());
+    neuron.set_bias(bias);
+
+    // no pulse is ever delivered to the network: only the bias drives the output Neuron,
+    // also on the time steps when it is just inhibited
+    let input = vec![vec![false; 10]; network.nr_inputs];
+    for engine in [
+        Engine::Sequential,
+        Engine::Threaded,
+        Engine::Pipelined,
+        Engine::Packed,
+    ] {
+        let mut snn = network.clone();
+        snn.set_engine(engine);
+        let output = snn.run(input.clone());
+        assert!(output[0].iter().all(|fired| *fired));
+        assert!(output[1..].iter().flatten().all(|fired| !*fired));
+    }
+    let concurrent_run = network.clone().run_concurrently(&input, &[]);
+    assert!(concurrent_run.output[0].iter().all(|fired| *fired));
+}
+
+#[test]
+#[should_panic(expected = "the window of a convolution must move by at least a position")]
+fn convolution_rejects_zero_stride() {
+    let mut network = convolutional_network();
+    let kernel = network.layers[0].kernel.clone().unwrap();
+    let convolution = Convolution {
+        stride: 0,
+        ..kernel.convolution
+    };
+    let weights = kernel.weights.iter().map(Register::stored_value).collect();
+    network.layers[0].set_kernel(convolution, weights);
+}
+
+#[test]
+#[should_panic(expected = "the input of a convolutional layer must have a position for each")]
+fn convolution_rejects_input_of_wrong_size() {
+    let mut network = convolutional_network();
+    // layer 0 now also receives the pulses fed back by layer 1
+    network.layers[0].sources = Some(vec![LayerSource::Input, LayerSource::Feedback(1)]);
+    run_with_engine(&network, Engine::Sequential, None);
+}
+
+#[test]
+fn probes_read_kernel_faults_through_every_neuron() {
+    let mut network = convolutional_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let mut probes = Probes::new();
+    // the damaged weight is probed through the Neuron it is damaged from and through
+    // another Neuron sharing the kernel
+    probes
+        .probe(0, 0, NeuronRegister::Weight(3))
+        .probe(0, 5, NeuronRegister::Weight(3));
+    let fault = Fault {
+        address: RegisterAddress {
+            layer: 0,
+            neuron: 0,
+            register: NeuronRegister::Weight(3),
+        },
+        damage: Damage::StuckAt1 { bit_position: 62 },
+    };
+
+    for engine in [
+        Engine::Sequential,
+        Engine::Threaded,
+        Engine::Pipelined,
+        Engine::Packed,
+    ] {
+        network.set_engine(engine);
+        let (golden, faulty) = network.trace_fault(fault, &input, &probes);
+        // the damaged weight may be NaN: values are compared by their bits
+        let bits = |values: &[f64]| {
+            values
+                .iter()
+                .map(|value| value.to_bits())
+                .collect::<Vec<_>>()
+        };
+        assert!(bits(&faulty.trace.values[0]) == bits(&faulty.trace.values[1]));
+        assert!(bits(&golden.trace.values[1]) != bits(&faulty.trace.values[1]));
+    }
+}
+
+#[test]
+fn probes_of_missing_registers_record_nan_in_every_engine() {
+    let network = active_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let time_steps_number = input[0].len();
+    let mut probes = Probes::new();
+    // a probe on a layer which does not exist, one on a neuron which does not exist and
+    // one on an existing register
+    probes
+        .probe(network.layers.len(), 0, NeuronRegister::MembranePotential)
+        .probe(
+            0,
+            network.layers[0].len(),
+            NeuronRegister::MembranePotential,
+        )
+        .probe(0, 0, NeuronRegister::MembranePotential);
+
+    let mut traces = Vec::new();
+    for engine in [
+        Engine::Sequential,
+        Engine::Threaded,
+        Engine::Pipelined,
+        Engine::Packed,
+    ] {
+        let mut snn = network.clone();
+        snn.set_engine(engine);
+        let trace = snn.run_with_probes(&input, &probes).trace;
+        for values in &trace.values[..2] {
+            assert!(values.len() == time_steps_number);
+            assert!(values.iter().all(|value| value.is_nan()));
+        }
+        assert!(trace.values[2].len() == time_steps_number);
+        assert!(trace.to_csv().lines().count() == time_steps_number + 1);
+
+        // NaN values are written as null and read back as NaN
+        let json = trace.to_json();
+        assert!(Trace::from_json(&json).to_json() == json);
+        traces.push(json);
+    }
+    assert!(traces.iter().all(|trace| *trace == traces[0]));
+}
+
+/// A neuron model which is not a built-in one: the membrane potential integrates pulses
+/// with no leakage, as IntegrateAndFire does
+struct CustomIntegrateAndFire;
+
+impl NeuronDynamics for CustomIntegrateAndFire {
+    fn update_membrane_potential(
+        &self,
+        neuron: &mut Neuron,
+        pulses_contribution: Register,
+        time_step: usize,
+        _time_step_duration_ms: f64,
+    ) {
+        Register::add(
+            neuron.v_mem,
+            pulses_contribution,
+            &mut neuron.add_reg,
+            time_step,
+        );
+        neuron.add_reg.copy_to(&mut neuron.v_mem, time_step);
+    }
+}
+
+#[test]
+fn networks_with_custom_models_are_not_saved() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    let path = std::env::temp_dir().join(format!("snn_custom_model_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    network.layers[1].neurons[2].model = Some(Arc::new(CustomIntegrateAndFire));
+    assert!(json::save_to_file(&network, path) == Err(json::CustomModelError));
+    assert!(!std::path::Path::new(path).exists());
+
+    network.layers[1].neurons[2].model = None;
+    assert!(json::save_to_file(&network, path).is_ok());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "the escape noise width must be greater than 0")]
+fn noise_rejects_zero_escape_noise_width() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    network.set_noise(NoiseModel {
+        membrane_noise_std: 0.0,
+        escape_noise_width: Some(0.0),
+        seed: 42,
+    });
+}
+
+#[test]
+#[should_panic(expected = "the escape noise width must be greater than 0")]
+fn loading_rejects_zero_escape_noise_width() {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    let path = std::env::temp_dir().join(format!("snn_invalid_noise_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    // the noise model is not validated when assigned directly
+    network.noise = Some(NoiseModel {
+        membrane_noise_std: 0.0,
+        escape_noise_width: Some(0.0),
+        seed: 42,
+    });
+    json::save_to_file(&network, path).unwrap();
+    let loaded = std::panic::catch_unwind(|| json::load_from_file(path));
+    std::fs::remove_file(path).unwrap();
+    std::panic::resume_unwind(loaded.err().unwrap());
+}
+
+/// Networks whose state carries pulses from a time step to the following ones: the
+/// active network, one with delayed synapses and one with feedback connections
+fn networks_with_pending_pulses() -> [Network; 3] {
+    let mut delayed_network = active_network();
+    for neuron in delayed_network.layers[0].neurons.iter_mut() {
+        neuron.set_delays((0..neuron.weights.len()).map(|i| i % 3).collect());
+    }
+    let mut feedback_network = active_network();
+    connect_sources(
+        &mut feedback_network,
+        vec![
+            Some(vec![LayerSource::Input, LayerSource::Feedback(2)]),
+            Some(vec![LayerSource::Layer(0), LayerSource::Feedback(1)]),
+            None,
+        ],
+    );
+    [active_network(), delayed_network, feedback_network]
+}
+
+#[test]
+fn split_runs_match_uninterrupted_runs() {
+    let time_steps_number = 60;
+    let mut rng = StdRng::seed_from_u64(42);
+
+    for network in networks_with_pending_pulses() {
+        for _ in 0..10 {
+            let input = (0..network.nr_inputs)
+                .map(|_| (0..time_steps_number).map(|_| rng.gen_bool(0.3)).collect())
+                .collect::<Vec<Vec<bool>>>();
+            let split_time_step = rng.gen_range(1..time_steps_number);
+            let (first_input, second_input): (Vec<Vec<bool>>, Vec<Vec<bool>>) = input
+                .iter()
+                .map(|entrance| {
+                    let (first, second) = entrance.split_at(split_time_step);
+                    (first.to_vec(), second.to_vec())
+                })
+                .unzip();
+            let expected = network.clone().run(input.clone());
+
+            for engine in [
+                Engine::Threaded,
+                Engine::Sequential,
+                Engine::Pipelined,
+                Engine::Packed,
+                Engine::Concurrent,
+            ] {
+                let mut snn = network.clone();
+                snn.set_engine(engine);
+
+                // the second run continues from the time step the first one stopped at
+                let mut output = snn.run_in_place(&first_input);
+                assert!(snn.time_step() == split_time_step);
+                for (output_node, second_output_node) in
+                    output.iter_mut().zip(snn.run_in_place(&second_input))
+                {
+                    output_node.extend(second_output_node);
+                }
+                assert!(snn.time_step() == time_steps_number);
+                assert!(output == expected);
+            }
+
+            // the concurrent simulation of faults continues from the same time step as well
+            let mut snn = network.clone();
+            let mut output = snn.run_concurrently(&first_input, &[]).output;
+            for (output_node, second_output_node) in output
+                .iter_mut()
+                .zip(snn.run_concurrently(&second_input, &[]).output)
+            {
+                output_node.extend(second_output_node);
+            }
+            assert!(output == expected);
+        }
+    }
+}
+
+#[test]
+fn reopened_sessions_match_uninterrupted_sessions() {
+    let time_steps_number = 60;
+    let mut rng = StdRng::seed_from_u64(7);
+
+    for network in networks_with_pending_pulses() {
+        for _ in 0..10 {
+            let input = (0..time_steps_number)
+                .map(|_| {
+                    (0..network.nr_inputs)
+                        .map(|_| rng.gen_bool(0.3))
+                        .collect::<Vec<bool>>()
+                })
+                .collect::<Vec<Vec<bool>>>();
+            let split_time_step = rng.gen_range(1..time_steps_number);
+
+            let mut expected_network = network.clone();
+            let mut expected_session = expected_network.open_session();
+            let expected = input
+                .iter()
+                .map(|pulses| expected_session.step_with_state(pulses))
+                .collect::<Vec<StepState>>();
+
+            // the session is dropped mid-stream and a new one continues where it stopped
+            let mut snn = network.clone();
+            let mut session = snn.open_session();
+            let mut states = input[..split_time_step]
+                .iter()
+                .map(|pulses| session.step_with_state(pulses))
+                .collect::<Vec<StepState>>();
+            drop(session);
+            assert!(snn.time_step() == split_time_step);
+            let mut session = snn.open_session();
+            assert!(session.time_step() == split_time_step);
+            states.extend(
+                input[split_time_step..]
+                    .iter()
+                    .map(|pulses| session.step_with_state(pulses)),
+            );
+            assert!(session.time_step() == time_steps_number);
+
+            for (state, expected_state) in states.iter().zip(&expected) {
+                assert!(state.output == expected_state.output);
+                assert!(state.emitted_pulses == expected_state.emitted_pulses);
+                assert!(state.membrane_potentials == expected_state.membrane_potentials);
+            }
+
+            // a session also continues a run
+            let mut snn = network.clone();
+            let first_input = (0..network.nr_inputs)
+                .map(|input_node| {
+                    input[..split_time_step]
+                        .iter()
+                        .map(|pulses| pulses[input_node])
+                        .collect()
+                })
+                .collect::<Vec<Vec<bool>>>();
+            snn.run_in_place(&first_input);
+            let mut session = snn.open_session();
+            for (pulses, expected_state) in input.iter().zip(&expected).skip(split_time_step) {
+                assert!(session.step(pulses) == expected_state.output);
+            }
+        }
+    }
+}
+
+#[test]
+fn parallel_neuron_updates_match_serial_ones() {
+    let network = active_network();
+    assert!(network
+        .layers
+        .iter()
+        .all(|layer| layer.inhibition == LateralInhibition::Dense));
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let mut probes = Probes::new();
+    probes.probe_all(&network, NeuronRegister::MembranePotential);
+    // the faults of the first Neuron of each layer, and the same ones on the last Neuron,
+    // which is updated by another thread
+    let faults = faults(&network)
+        .into_iter()
+        .flat_map(|fault| {
+            let last_neuron = network.layers[fault.address.layer].len() - 1;
+            let mut last_neuron_fault = fault;
+            last_neuron_fault.address.neuron = last_neuron;
+            [fault, last_neuron_fault]
+        })
+        .collect::<Vec<Fault>>();
+
+    for fault in faults.iter().copied().map(Some).chain([None]) {
+        let mut serial = network.clone();
+        serial.set_engine(Engine::Sequential);
+        // every layer is split in chunks of different sizes
+        let mut parallel = serial.clone();
+        parallel.set_neuron_parallelism(Some(NeuronParallelism {
+            min_neurons: 2,
+            threads: 3,
+        }));
+        if let Some(fault) = fault {
+            assert!(serial.apply_damage(fault));
+            assert!(parallel.apply_damage(fault));
+        }
+
+        // the output, the values of the membrane potentials at every time step and the
+        // final state are the same
+        let serial_run = serial.run_with_probes(&input, &probes);
+        let parallel_run = parallel.run_with_probes(&input, &probes);
+        assert!(parallel_run.output == serial_run.output);
+        assert!(parallel_run.trace.to_json() == serial_run.trace.to_json());
+        for (parallel_layer, serial_layer) in parallel.layers.iter().zip(&serial.layers) {
+            assert!(parallel_layer.has_same_state(serial_layer));
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use snn::network::dynamics::IntegrateAndFire;
+use snn::network::json::{self, InputMatrix};
+use snn::network::neuron::{Neuron, NeuronRegister};
+use snn::network::probe::Probes;
+use snn::network::{Fault, Homeostasis, LateralInhibition, Network, RegisterAddress, ResetMode};
+use snn::register::Damage;
+
+const NETWORK_JSON: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/sources/snn_data.json");
+const INPUT_JSON: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/sources/simulation_input.json");
+
+/// The network of the sources, with weaker lateral inhibition so that the input keeps
+/// it firing for the whole simulation
+fn active_network() -> Network {
+    let mut network = json::load_from_file(NETWORK_JSON);
+    for neuron in network
+        .layers
+        .iter_mut()
+        .flat_map(|layer| layer.neurons.iter_mut())
+    {
+        let internal_weights = neuron
+            .internal_weights
+            .iter()
+            .map(|weight| weight.read_value(None).unwrap() * 0.3)
+            .collect();
+        neuron.set_internal_weights(internal_weights);
+    }
+    network
+}
+
+#[test]
+fn delayed_synapses_deliver_pulses_after_their_delay() {
+    let mut neuron = Neuron::new(-55.0, -70.0, -70.0, 20.0);
+    neuron.set_weights(vec![1.0; 3]);
+    neuron.set_delays(vec![0, 2, 5]);
+
+    // the pulse on the synapse with no delay is delivered immediately, the others are
+    // buffered until their delay expires
+    assert!(neuron.receive_pulses(&[0, 1, 2], 1) == vec![0]);
+    assert!(neuron.receive_pulses(&[], 2).is_empty());
+    assert!(neuron.receive_pulses(&[0], 3) == vec![0, 1]);
+    assert!(neuron.receive_pulses(&[], 5).is_empty());
+    assert!(neuron.receive_pulses(&[1], 6) == vec![2]);
+    assert!(neuron.receive_pulses(&[], 8) == vec![1]);
+}
+
+#[test]
+fn reset_modes_leave_the_expected_membrane_potential() {
+    let (v_th, v_rest, v_reset) = (-55.0, -70.0, -65.0);
+    // a single pulse brings the membrane potential above the threshold
+    let weight = 20.0;
+    let v_mem_before_reset = v_rest + weight;
+
+    for reset_mode in [
+        ResetMode::ToResetPotential,
+        ResetMode::BySubtraction,
+        ResetMode::NoReset,
+    ] {
+        let mut neuron = Neuron::new(v_th, v_rest, v_reset, 20.0);
+        neuron.set_weights(vec![weight]);
+        assert!(neuron.feed_pulses(&vec![0], 0, 1.0, &IntegrateAndFire, None, reset_mode));
+
+        let expected = match reset_mode {
+            ResetMode::ToResetPotential => v_reset,
+            // the residual above the threshold is kept
+            ResetMode::BySubtraction => v_mem_before_reset - v_th,
+            ResetMode::NoReset => v_mem_before_reset,
+        };
+        assert!(neuron.v_mem.read_value(None).unwrap() == expected);
+    }
+}
+
+#[test]
+fn output_stays_aligned_across_silent_time_steps() {
+    let network = json::load_from_file(NETWORK_JSON);
+    let time_steps_number = 8;
+
+    // all the entrances receive a pulse at a single time step, preceded and followed by
+    // time steps in which no layer receives or emits any pulse
+    for pulse_time_step in [0, 4, time_steps_number - 1] {
+        let mut input = vec![vec![false; time_steps_number]; network.nr_inputs];
+        for entrance in input.iter_mut() {
+            entrance[pulse_time_step] = true;
+        }
+
+        let output = network.clone().run(input);
+        // the pulses cross the whole network within the time step they are received
+        for output_node in output {
+            for (time_step, fired) in output_node.into_iter().enumerate() {
+                assert!(fired == (time_step == pulse_time_step));
+            }
+        }
+    }
+}
+
+#[test]
+fn reset_state_allows_reusing_the_network() {
+    let network = json::load_from_file(NETWORK_JSON);
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let expected = network.clone().run(input.clone());
+    let membrane_potentials = |network: &Network| {
+        network
+            .layers
+            .iter()
+            .flat_map(|layer| layer.neurons.iter())
+            .map(|neuron| neuron.v_mem.read_value(None).unwrap())
+            .collect::<Vec<f64>>()
+    };
+
+    let mut snn = network.clone();
+    assert!(snn.run_in_place(&input) == expected);
+    // the state reached at the end of the run is kept...
+    assert!(membrane_potentials(&snn) != membrane_potentials(&network));
+
+    // ...until it is reset, then the network runs as a fresh one
+    snn.reset_state();
+    assert!(membrane_potentials(&snn) == membrane_potentials(&network));
+    assert!(snn.run_in_place(&input) == expected);
+}
+
+#[test]
+fn session_matches_run() {
+    let network = active_network();
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let time_steps_number = input[0].len();
+    let middle_time_step = time_steps_number / 2;
+    let input_at = |time_step: usize| {
+        input
+            .iter()
+            .map(|entrance| entrance[time_step])
+            .collect::<Vec<bool>>()
+    };
+    // a transient fault injected mid-session affects the run as if it had been applied
+    // before running
+    let fault = Fault {
+        address: RegisterAddress {
+            layer: network.layers.len() - 1,
+            neuron: 0,
+            register: NeuronRegister::Threshold,
+        },
+        damage: Damage::TransientBitFlip {
+            bit_position: 63,
+            time_step: middle_time_step,
+        },
+    };
+
+    let mut outputs = Vec::new();
+    for fault in [None, Some(fault)] {
+        let mut expected_network = network.clone();
+        if let Some(fault) = fault {
+            assert!(expected_network.apply_damage(fault));
+        }
+        let expected = expected_network.run(input.clone());
+
+        let mut snn = network.clone();
+        let mut session = snn.open_session();
+        for time_step in 0..time_steps_number {
+            if time_step == middle_time_step {
+                if let Some(fault) = fault {
+                    assert!(session.apply_damage(fault));
+                }
+            }
+            let state = session.step_with_state(&input_at(time_step));
+            assert!(session.time_step() == time_step + 1);
+
+            // the state matches the output and the network left by the time step
+            let fired_output_nodes = (0..state.output.len())
+                .filter(|output_node| state.output[*output_node])
+                .collect::<Vec<usize>>();
+            assert!(state.emitted_pulses.last() == Some(&fired_output_nodes));
+            for (layer, membrane_potentials) in session
+                .network()
+                .layers
+                .iter()
+                .zip(&state.membrane_potentials)
+            {
+                assert!(layer
+                    .neurons
+                    .iter()
+                    .map(|neuron| neuron.v_mem.stored_value())
+                    .eq(membrane_potentials.iter().copied()));
+            }
+
+            assert!(state
+                .output
+                .iter()
+                .eq(expected.iter().map(|output_node| &output_node[time_step])));
+        }
+        outputs.push(expected);
+    }
+    assert!(outputs[0] != outputs[1]);
+}
+
+#[test]
+fn k_winner_take_all_lets_at_most_k_neurons_fire() {
+    let mut network = active_network();
+    let k = 2;
+    let time_steps_number = 10;
+    // all the entrances receive a pulse at every time step
+    let input = vec![true; network.nr_inputs];
+    let max_emitted_pulses = |network: &mut Network| {
+        let mut session = network.open_session();
+        (0..time_steps_number)
+            .flat_map(|_| session.step_with_state(&input).emitted_pulses)
+            .map(|pulses| pulses.len())
+            .max()
+            .unwrap()
+    };
+
+    assert!(max_emitted_pulses(&mut network.clone()) > k);
+    for layer in network.layers.iter_mut() {
+        layer.inhibition = LateralInhibition::KWinnerTakeAll { k };
+    }
+    assert!(max_emitted_pulses(&mut network) == k);
+}
+
+/// The thresholds of all the Neurons of 'network', for each layer
+fn thresholds(network: &Network) -> Vec<Vec<f64>> {
+    network
+        .layers
+        .iter()
+        .map(|layer| {
+            layer
+                .neurons
+                .iter()
+                .map(|neuron| neuron.v_th.stored_value())
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn homeostasis_moves_thresholds_towards_the_target_rate() {
+    let mut network = active_network();
+    let homeostasis = Homeostasis {
+        target_rate: 0.5,
+        window: 5,
+        learning_rate: 2.0,
+    };
+    for layer in network.layers.iter_mut() {
+        layer.homeostasis = Some(homeostasis);
+    }
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let initial_thresholds = thresholds(&network);
+
+    let mut snn = network.clone();
+    let mut session = snn.open_session();
+    let mut spike_counts = initial_thresholds
+        .iter()
+        .map(|layer| vec![0; layer.len()])
+        .collect::<Vec<Vec<usize>>>();
+    for time_step in 0..homeostasis.window {
+        let input = input
+            .iter()
+            .map(|entrance| entrance[time_step])
+            .collect::<Vec<bool>>();
+        let state = session.step_with_state(&input);
+        for (layer, emitted_pulses) in state.emitted_pulses.into_iter().enumerate() {
+            for neuron in emitted_pulses {
+                spike_counts[layer][neuron] += 1;
+            }
+        }
+    }
+
+    // at the end of the window each threshold is raised if its Neuron fired more often
+    // than the target rate, lowered otherwise
+    let expected_thresholds = initial_thresholds
+        .iter()
+        .zip(&spike_counts)
+        .map(|(layer_thresholds, layer_spike_counts)| {
+            layer_thresholds
+                .iter()
+                .zip(layer_spike_counts)
+                .map(|(v_th, spike_count)| {
+                    let firing_rate = *spike_count as f64 / homeostasis.window as f64;
+                    v_th + homeostasis.learning_rate * (firing_rate - homeostasis.target_rate)
+                })
+                .collect::<Vec<f64>>()
+        })
+        .collect::<Vec<Vec<f64>>>();
+    assert!(thresholds(session.network()) == expected_thresholds);
+    assert!(expected_thresholds != initial_thresholds);
+}
+
+#[test]
+fn faulty_runs_start_from_the_golden_thresholds() {
+    let mut network = active_network();
+    for layer in network.layers.iter_mut() {
+        layer.homeostasis = Some(Homeostasis {
+            target_rate: 0.5,
+            window: 2,
+            learning_rate: 2.0,
+        });
+    }
+    let input = InputMatrix::load_from_file(INPUT_JSON).0;
+    let mut probes = Probes::new();
+    for (layer_nr, layer) in network.layers.iter().enumerate() {
+        for neuron_nr in 0..layer.neurons.len() {
+            probes.probe(layer_nr, neuron_nr, NeuronRegister::Threshold);
+        }
+    }
+    let mut adapted_network = network.clone();
+    adapted_network.run_in_place(&input);
+    assert!(thresholds(&adapted_network) != thresholds(&network));
+
+    // the sign bit of the negative threshold is already set, so the fault changes nothing:
+    // the faulty run matches the golden one only if it starts from the same thresholds,
+    // not from those adapted during the golden run
+    let fault = Fault {
+        address: RegisterAddress {
+            layer: 0,
+            neuron: 0,
+            register: NeuronRegister::Threshold,
+        },
+        damage: Damage::StuckAt1 { bit_position: 63 },
+    };
+    let (golden, faulty) = network.trace_fault(fault, &input, &probes);
+    assert!(faulty.output == golden.output);
+    assert!(faulty.trace.values == golden.trace.values);
+}
+
+#[test]
+fn homeostasis_state_is_saved_only_for_regulated_layers() {
+    let mut network = active_network();
+    network.layers[1].homeostasis = Some(Homeostasis {
+        target_rate: 0.5,
+        window: 4,
+        learning_rate: 2.0,
+    });
+    let path = std::env::temp_dir().join(format!("snn_homeostasis_{}.json", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    json::save_to_file(&network, path).unwrap();
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    std::fs::remove_file(path).unwrap();
+    for (layer_nr, layer) in saved["layers"].as_array().unwrap().iter().enumerate() {
+        for neuron in layer["neurons"].as_array().unwrap() {
+            assert!(neuron.get("homeostasis_state").is_some() == (layer_nr == 1));
+        }
+    }
+}